@@ -0,0 +1,68 @@
+use std::str::FromStr;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use edmipng::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    ihdr::{self, Ihdr},
+    png::{Png, PngRef},
+    stream::ChunkReader,
+};
+
+const IDAT_CHUNK_SIZE: usize = 64 * 1024;
+const IDAT_CHUNK_COUNT: usize = 256;
+
+/// Builds 16 MiB file; image data is random as it's never decompressed while parsing
+fn large_png() -> Vec<u8> {
+    let ihdr = Ihdr {
+        width: 4096,
+        height: 1024,
+        bit_depth: 8,
+        color_type: ihdr::TRUECOLOR,
+        compression_method: 0,
+        filter_method: 0,
+        interlace_method: 0,
+    };
+    let mut state: u32 = 0x2545_F491;
+    let mut chunks = vec![ihdr.to_chunk().unwrap()];
+    for _ in 0..IDAT_CHUNK_COUNT {
+        let data = (0..IDAT_CHUNK_SIZE)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                state as u8
+            })
+            .collect();
+        chunks.push(Chunk::new(ChunkType::from_str("IDAT").unwrap(), data));
+    }
+    chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()));
+    Png::from_chunks(chunks).as_bytes()
+}
+
+fn parse(c: &mut Criterion) {
+    let bytes = large_png();
+    let mut group = c.benchmark_group("parse");
+    group.throughput(Throughput::Bytes(bytes.len() as u64));
+    group.sample_size(20);
+
+    group.bench_function("PngRef::parse (borrowed)", |b| {
+        b.iter(|| PngRef::parse(black_box(&bytes)).unwrap().chunks().len())
+    });
+    group.bench_function("Png::try_from (owned)", |b| {
+        b.iter(|| Png::try_from(black_box(bytes.as_slice())).unwrap())
+    });
+    group.bench_function("Png::from_reader (owned, streamed)", |b| {
+        b.iter(|| Png::from_reader(black_box(bytes.as_slice())).unwrap())
+    });
+    group.bench_function("ChunkReader (skipping data)", |b| {
+        b.iter(|| {
+            let mut reader = ChunkReader::new(black_box(bytes.as_slice())).unwrap();
+            while reader.next_header().unwrap().is_some() {}
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, parse);
+criterion_main!(benches);
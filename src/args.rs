@@ -1,6 +1,8 @@
 use std::path::PathBuf;
 
-use clap::{Args, Parser, Subcommand};
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use edmipng::{encoder, payload};
 
 /// EDMIPNG - Encode and Decode Messages In PNG
 #[derive(Debug, Parser)]
@@ -18,20 +20,104 @@ pub enum Command {
     Decode(DecodeArgs),
     /// Remove chunk with message from png
     Remove(RemoveArgs),
+    /// Replace message stored in existing chunk, keeping its position
+    Update(UpdateArgs),
     /// Print all chunks with encoded messages
     Print(PrintArgs),
+    /// List every chunk with its position, properties and decoded content of standard chunks
+    Inspect(InspectArgs),
+    /// Print bytes of chunk as hexdump, base64 or raw bytes
+    Dump(DumpArgs),
+    /// Write data of chunks to separate files
+    Extract(ExtractArgs),
+    /// Write data of every chunk to separate files, together with manifest describing their order
+    Export(ExportArgs),
+    /// Build png from chunk data files listed in manifest
+    Pack(PackArgs),
+    /// Generate key pair for encrypting messages to recipients or signing them
+    Keygen(KeygenArgs),
+    /// Verify signatures of signed chunks
+    Verify(VerifyArgs),
+    /// Manage standard textual metadata chunks (tEXt, zTXt, iTXt)
+    Text(TextArgs),
+    /// Create new carrier image filled with solid colour or random noise
+    Create(CreateArgs),
+    /// Check that png follows chunk ordering rules of the specification
+    Validate(ValidateArgs),
+    /// Fix corrupted CRCs and drop unreadable data at the end of png
+    Repair(RepairArgs),
 }
 
 #[derive(Debug, Args)]
 pub struct EncodeArgs {
-    /// Path to local png file / url to external png file
+    /// Path to local png file / url to external png file / `-` for standard input
     pub source: String,
     /// Chunk type of the chunk to be created in which message will be encoded
     pub chunk_type: String,
     /// Message to be encoded inside the chunk
-    pub message: String,
+    #[clap(required_unless_present_any = ["file", "stdin"])]
+    pub message: Option<String>,
     /// Path to output file (if not provided, depending on the source of the file, changes are made to the source file or new file is automatically created)
     pub output_file: Option<PathBuf>,
+    /// Path to output file (alternative to positional output file, useful together with --file and --stdin)
+    #[clap(
+        short,
+        long = "output",
+        value_name = "OUTPUT_FILE",
+        conflicts_with = "output_file"
+    )]
+    pub output: Option<PathBuf>,
+    /// Write edited png to standard output (same as `-o -`, default when source is `-`)
+    #[clap(long, conflicts_with_all = ["output_file", "output"])]
+    pub stdout: bool,
+    /// Embed content of given file (together with its name and mime type) instead of message
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["message", "stdin"])]
+    pub file: Option<PathBuf>,
+    /// Embed data read from standard input instead of message
+    #[clap(long, conflicts_with = "message")]
+    pub stdin: bool,
+    /// Mime type of embedded data (by default guessed from file extension)
+    #[clap(long, value_name = "TYPE")]
+    pub mime_type: Option<String>,
+    /// Insert new chunk right before first chunk with given type (by default it's inserted right before IEND)
+    #[clap(long, value_name = "TYPE", conflicts_with_all = ["after", "index"])]
+    pub before: Option<String>,
+    /// Insert new chunk right after last chunk with given type
+    #[clap(long, value_name = "TYPE", conflicts_with = "index")]
+    pub after: Option<String>,
+    /// Insert new chunk at given index in the chunk list
+    #[clap(long, value_name = "N")]
+    pub index: Option<usize>,
+    /// Encrypt message with passphrase (read from EDMIPNG_PASSPHRASE or prompted for)
+    #[clap(long, conflicts_with = "recipient")]
+    pub encrypt: bool,
+    /// Encrypt message to recipient with public key stored in given file (can be repeated)
+    #[clap(long, value_name = "PUBKEY_FILE")]
+    pub recipient: Vec<PathBuf>,
+    /// Sign chunk with secret signing key stored in given file
+    #[clap(long, value_name = "SECRET_KEY_FILE")]
+    pub sign: Option<PathBuf>,
+    /// Compress message with deflate before storing it
+    #[clap(long)]
+    pub compress: bool,
+    /// Split message into multiple chunks carrying at most given number of bytes each
+    #[clap(long, value_name = "BYTES")]
+    pub fragment_size: Option<usize>,
+    /// Where message is stored
+    #[clap(long, value_enum, default_value_t = Carrier::Chunk)]
+    pub carrier: Carrier,
+    /// Don't copy data stored after IEND chunk to output file
+    #[clap(long)]
+    pub strip_trailing_data: bool,
+}
+
+/// Place in which message is hidden
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Carrier {
+    /// Dedicated ancillary chunk
+    Chunk,
+    /// Least significant bits of pixel data (8-bit non-interlaced greyscale or truecolor images only, chunk type is still used to authenticate encrypted messages)
+    Lsb,
 }
 
 #[derive(Debug, Args)]
@@ -40,20 +126,346 @@ pub struct DecodeArgs {
     pub source: String,
     /// Chunk type of the chunk containg message to decode
     pub chunk_type: String,
+    /// Secret key file used to decrypt message encrypted to recipients
+    #[clap(long, value_name = "KEYFILE")]
+    pub identity: Option<PathBuf>,
+    /// Maximum size of decompressed message in bytes
+    #[clap(long, value_name = "BYTES", default_value_t = payload::DEFAULT_MAX_SIZE)]
+    pub max_size: u64,
+    /// Write exact decoded bytes to given file
+    #[clap(long, value_name = "PATH", conflicts_with = "raw")]
+    pub output: Option<PathBuf>,
+    /// Write exact decoded bytes to standard output
+    #[clap(long)]
+    pub raw: bool,
+    /// Where message is stored
+    #[clap(long, value_enum, default_value_t = Carrier::Chunk)]
+    pub carrier: Carrier,
+    /// Read file despite CRC mismatches and broken data at its end (problems are reported as warnings)
+    #[clap(long)]
+    pub lenient: bool,
+    #[clap(flatten)]
+    pub selector: SelectorArgs,
+    /// Output format (ignored when message is written with --output or --raw)
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+/// Which of the chunks with given type should be used (the first one by default)
+#[derive(Debug, Args)]
+pub struct SelectorArgs {
+    /// Use every chunk with given type
+    #[clap(long, conflicts_with_all = ["index", "nth"])]
+    pub all: bool,
+    /// Use chunk at given index in the chunk list (it must have given type)
+    #[clap(long, value_name = "N", conflicts_with = "nth")]
+    pub index: Option<usize>,
+    /// Use N-th chunk with given type, counting from 0
+    #[clap(long, value_name = "N")]
+    pub nth: Option<usize>,
 }
 
 #[derive(Debug, Args)]
 pub struct RemoveArgs {
-    /// Path to local png file / url to external png file
+    /// Path to local png file / url to external png file / `-` for standard input
     pub source: String,
     /// Chunk type of the chunk to be removed
     pub chunk_type: String,
     /// Path to output file (if not provided, depending on the source of the file, changes are made to the source file or new file is automatically created)
     pub output_file: Option<PathBuf>,
+    /// Path to output file (alternative to positional output file)
+    #[clap(
+        short,
+        long = "output",
+        value_name = "OUTPUT_FILE",
+        conflicts_with = "output_file"
+    )]
+    pub output: Option<PathBuf>,
+    /// Write edited png to standard output (same as `-o -`, default when source is `-`)
+    #[clap(long, conflicts_with_all = ["output_file", "output"])]
+    pub stdout: bool,
+    /// Don't copy data stored after IEND chunk to output file
+    #[clap(long)]
+    pub strip_trailing_data: bool,
+    #[clap(flatten)]
+    pub selector: SelectorArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct UpdateArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Chunk type of the chunk containing message to replace
+    pub chunk_type: String,
+    /// New message to be encoded inside the chunk
+    #[clap(required_unless_present_any = ["file", "stdin"])]
+    pub message: Option<String>,
+    /// Path to output file (if not provided, depending on the source of the file, changes are made to the source file or new file is automatically created)
+    pub output_file: Option<PathBuf>,
+    /// Path to output file (alternative to positional output file, useful together with --file and --stdin)
+    #[clap(
+        short,
+        long = "output",
+        value_name = "OUTPUT_FILE",
+        conflicts_with = "output_file"
+    )]
+    pub output: Option<PathBuf>,
+    /// Write edited png to standard output (same as `-o -`, default when source is `-`)
+    #[clap(long, conflicts_with_all = ["output_file", "output"])]
+    pub stdout: bool,
+    /// Embed content of given file (together with its name and mime type) instead of message
+    #[clap(long, value_name = "PATH", conflicts_with_all = ["message", "stdin"])]
+    pub file: Option<PathBuf>,
+    /// Embed data read from standard input instead of message
+    #[clap(long, conflicts_with = "message")]
+    pub stdin: bool,
+    /// Mime type of embedded data (by default guessed from file extension)
+    #[clap(long, value_name = "TYPE")]
+    pub mime_type: Option<String>,
+    /// Encrypt message with passphrase (read from EDMIPNG_PASSPHRASE or prompted for)
+    #[clap(long, conflicts_with = "recipient")]
+    pub encrypt: bool,
+    /// Encrypt message to recipient with public key stored in given file (can be repeated)
+    #[clap(long, value_name = "PUBKEY_FILE")]
+    pub recipient: Vec<PathBuf>,
+    /// Compress message with deflate before storing it
+    #[clap(long)]
+    pub compress: bool,
+    /// Sign updated chunk with secret signing key stored in given file (otherwise its old signature is removed)
+    #[clap(long, value_name = "SECRET_KEY_FILE")]
+    pub sign: Option<PathBuf>,
+    #[clap(flatten)]
+    pub selector: SelectorArgs,
 }
 
 #[derive(Debug, Args)]
 pub struct PrintArgs {
     /// Path to local png file / url to external png file
     pub source: String,
+    /// Output format
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct InspectArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Output format
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct DumpArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Chunk type of the chunk to be dumped, or its index in the chunk list
+    pub chunk: String,
+    /// How bytes are printed
+    #[clap(long, value_enum, default_value_t = DumpMode::Hex)]
+    pub mode: DumpMode,
+    /// Dump every chunk with given type instead of the first one
+    #[clap(long)]
+    pub all: bool,
+    /// Dump whole serialized chunk (length, type, data and CRC) instead of its data
+    #[clap(long)]
+    pub whole: bool,
+}
+
+/// How bytes are printed by dump
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum DumpMode {
+    /// Hex and ascii columns with byte offsets in file
+    Hex,
+    /// Base64 encoded bytes
+    Base64,
+    /// Bytes as they are
+    Raw,
+}
+
+#[derive(Debug, Args)]
+pub struct ExtractArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Chunk type of the chunks to be extracted (every chunk is extracted if not provided)
+    pub chunk_type: Option<String>,
+    /// Directory in which files are created
+    #[clap(short, long, value_name = "DIR", default_value = ".")]
+    pub dir: PathBuf,
+    /// Write whole serialized chunks (length, type, data and CRC) instead of their data
+    #[clap(long)]
+    pub whole: bool,
+    #[clap(flatten)]
+    pub selector: SelectorArgs,
+}
+
+#[derive(Debug, Args)]
+pub struct ExportArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Directory in which chunk files and manifest are created
+    #[clap(short, long, value_name = "DIR", default_value = ".")]
+    pub dir: PathBuf,
+    /// Format of the manifest
+    #[clap(long, value_enum, default_value_t = ManifestFormat::Json)]
+    pub manifest_format: ManifestFormat,
+}
+
+/// How manifest is written
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ManifestFormat {
+    /// manifest.json
+    Json,
+    /// manifest.toml
+    Toml,
+}
+
+#[derive(Debug, Args)]
+pub struct PackArgs {
+    /// Path to manifest (read as TOML if it has .toml extension, as JSON otherwise)
+    pub manifest: PathBuf,
+    /// Path to png file to be created
+    pub output_file: PathBuf,
+}
+
+/// How results are printed
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// JSON document
+    Json,
+    /// YAML document
+    Yaml,
+    /// Table with aligned columns
+    Table,
+}
+
+#[derive(Debug, Args)]
+pub struct KeygenArgs {
+    /// Path to secret key file to be created (public key is saved next to it with `.pub` extension)
+    pub output_file: PathBuf,
+    /// Generate Ed25519 signing key pair instead of encryption one
+    #[clap(long)]
+    pub signing: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct VerifyArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Public signing key file of trusted signer (can be repeated)
+    #[clap(long, value_name = "PUBKEY_FILE")]
+    pub trusted: Vec<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct TextArgs {
+    #[clap(subcommand)]
+    pub command: TextCommand,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum TextCommand {
+    /// List all textual chunks
+    List(TextListArgs),
+    /// Print text stored under keyword
+    Get(TextGetArgs),
+    /// Store text under keyword, replacing existing one
+    Set(TextSetArgs),
+    /// Remove all textual chunks with keyword
+    Remove(TextRemoveArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct TextListArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TextGetArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Keyword of the text
+    pub keyword: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TextSetArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Keyword of the text
+    pub keyword: String,
+    /// Text to be stored
+    pub text: String,
+    /// Path to output file (if not provided, depending on the source of the file, changes are made to the source file or new file is automatically created)
+    pub output_file: Option<PathBuf>,
+    /// Compress text (zTXt, or compressed iTXt for international text)
+    #[clap(long)]
+    pub compress: bool,
+    /// Store text as UTF-8 inside iTXt chunk (used automatically if text isn't valid Latin-1)
+    #[clap(long)]
+    pub international: bool,
+    /// Language tag of international text (e.g. en-US)
+    #[clap(long, default_value = "")]
+    pub language: String,
+    /// Keyword translated to the language of international text
+    #[clap(long, default_value = "")]
+    pub translated_keyword: String,
+}
+
+#[derive(Debug, Args)]
+pub struct TextRemoveArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Keyword of the text
+    pub keyword: String,
+    /// Path to output file (if not provided, depending on the source of the file, changes are made to the source file or new file is automatically created)
+    pub output_file: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+pub struct CreateArgs {
+    /// Path to png file to be created
+    pub output_file: PathBuf,
+    /// Image width in pixels
+    #[clap(long)]
+    pub width: u32,
+    /// Image height in pixels
+    #[clap(long)]
+    pub height: u32,
+    /// Fill colour as hex RRGGBB or RRGGBBAA (image gets alpha channel in the latter case)
+    #[clap(long, default_value = "ffffff", conflicts_with = "noise")]
+    pub color: String,
+    /// Fill image with random noise instead of solid colour (good cover for --carrier lsb)
+    #[clap(long)]
+    pub noise: bool,
+    /// Maximum number of bytes stored in single IDAT chunk
+    #[clap(long, value_name = "BYTES", default_value_t = encoder::DEFAULT_IDAT_CHUNK_SIZE)]
+    pub idat_size: usize,
+}
+
+#[derive(Debug, Args)]
+pub struct ValidateArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Report CRC mismatches and broken data at the end of file instead of failing on the first one
+    #[clap(long)]
+    pub lenient: bool,
+    /// Output format
+    #[clap(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub format: OutputFormat,
+}
+
+#[derive(Debug, Args)]
+pub struct RepairArgs {
+    /// Path to local png file / url to external png file
+    pub source: String,
+    /// Path to output file (if not provided, depending on the source of the file, changes are made to the source file or new file is automatically created)
+    pub output_file: Option<PathBuf>,
+    /// Drop data stored after IEND chunk
+    #[clap(long)]
+    pub strip_trailing_data: bool,
 }
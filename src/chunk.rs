@@ -1,14 +1,23 @@
+//! Single png chunk: length, type, data and CRC.
+
 use std::fmt::Display;
 
-use crate::chunk_type::ChunkType;
-use anyhow::{bail, Context, Error, Result};
-use crc::{Crc, CRC_32_ISO_HDLC};
+use crate::{chunk_type::ChunkType, error::PngError};
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use crc::{Crc, Table, CRC_32_ISO_HDLC};
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 
 pub(crate) const LENGTH_FIELD_LEN: usize = 4;
+/// Largest length of chunk data allowed by png specification (2^31 - 1)
+pub const MAX_LENGTH: u32 = (1 << 31) - 1;
 const CHUNK_TYPE_FIELD_LEN: usize = 4;
 const CRC_FIELD_LEN: usize = 4;
+/// Slice-by-16 table, CRC is the slowest part of parsing large files
+pub(crate) static CRC_32: Crc<u32, Table<16>> = Crc::<u32, Table<16>>::new(&CRC_32_ISO_HDLC);
 
 // http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
+/// Owned chunk, CRC is computed when it is created
 pub struct Chunk {
     data: Vec<u8>,
     chunk_type: ChunkType,
@@ -17,7 +26,16 @@ pub struct Chunk {
 }
 
 impl Chunk {
+    /// Creates chunk with given type and data, computing its CRC
+    ///
+    /// # Panics
+    /// If data is longer than [`MAX_LENGTH`] (use [`Chunk::try_new`] for data of unknown length)
     pub fn new(chunk_type: ChunkType, data: Vec<u8>) -> Chunk {
+        assert!(
+            data.len() <= MAX_LENGTH as usize,
+            "Chunk data is too long: {} bytes",
+            data.len()
+        );
         let length = data.len() as u32;
         let crc = Self::calculate_crc(&chunk_type, &data);
         Chunk {
@@ -28,6 +46,19 @@ impl Chunk {
         }
     }
 
+    /// Creates chunk with given type and data, refusing data longer than [`MAX_LENGTH`]
+    pub fn try_new(chunk_type: ChunkType, data: Vec<u8>) -> Result<Chunk> {
+        if data.len() > MAX_LENGTH as usize {
+            bail!(
+                "Chunk data is too long: {} bytes (at most {} allowed)",
+                data.len(),
+                MAX_LENGTH
+            );
+        }
+        Ok(Chunk::new(chunk_type, data))
+    }
+
+    /// Length of chunk data in bytes
     pub fn length(&self) -> u32 {
         self.length
     }
@@ -40,14 +71,22 @@ impl Chunk {
         &self.data
     }
 
+    /// CRC of chunk type and data
     pub fn crc(&self) -> u32 {
         self.crc
     }
 
+    /// Borrows the chunk without copying its data
+    pub fn as_chunk_ref(&self) -> ChunkRef<'_> {
+        ChunkRef::new(self.chunk_type.clone(), &self.data, self.crc)
+    }
+
+    /// Returns data as utf-8 string, failing if it is not valid utf-8
     pub fn data_as_string(&self) -> Result<String> {
         String::from_utf8(self.data.clone()).context("Data is not valid utf-8 string")
     }
 
+    /// Serializes chunk (length, type, data and CRC)
     pub fn as_bytes(&self) -> Vec<u8> {
         let length = Self::length_in_bytes(self.length);
 
@@ -59,82 +98,216 @@ impl Chunk {
         bytes
     }
 
+    /// Size of serialized chunk with data of given length
     pub fn length_in_bytes(data_length: u32) -> usize {
         LENGTH_FIELD_LEN + CHUNK_TYPE_FIELD_LEN + data_length as usize + CRC_FIELD_LEN
     }
 
+    /// Computes CRC over chunk type and then data, without joining them into one buffer
     fn calculate_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
-        let crc_instance = Crc::<u32>::new(&CRC_32_ISO_HDLC);
-        let bytes: Vec<u8> = chunk_type
-            .bytes()
-            .iter()
-            .copied()
-            .chain(data.iter().copied())
-            .collect();
-        crc_instance.checksum(&bytes)
+        let mut digest = CRC_32.digest();
+        digest.update(&chunk_type.bytes());
+        digest.update(data);
+        digest.finalize()
+    }
+}
+
+/// Chunk as stored in text formats, data is base64 encoded so that any bytes survive
+#[derive(Deserialize)]
+struct SerializedChunk {
+    chunk_type: ChunkType,
+    /// Optional when deserializing, checked against data if present
+    length: Option<u32>,
+    /// Optional when deserializing, checked against data if present
+    crc: Option<u32>,
+    #[serde(deserialize_with = "deserialize_base64")]
+    data: Vec<u8>,
+}
+
+impl Serialize for Chunk {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Chunk", 4)?;
+        state.serialize_field("chunk_type", &self.chunk_type)?;
+        state.serialize_field("length", &self.length)?;
+        state.serialize_field("crc", &self.crc)?;
+        state.serialize_field("data", &STANDARD.encode(&self.data))?;
+        state.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Chunk {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedChunk::deserialize(deserializer)?;
+        let chunk = Chunk::try_new(serialized.chunk_type, serialized.data)
+            .map_err(|err| de::Error::custom(err.to_string()))?;
+        if serialized
+            .length
+            .is_some_and(|length| length != chunk.length)
+        {
+            return Err(de::Error::custom(format!(
+                "Length of chunk {} doesn't match its data",
+                chunk.chunk_type
+            )));
+        }
+        if serialized.crc.is_some_and(|crc| crc != chunk.crc) {
+            return Err(de::Error::custom(format!(
+                "CRC of chunk {} doesn't match its content",
+                chunk.chunk_type
+            )));
+        }
+        Ok(chunk)
     }
 }
 
+/// Deserializes bytes stored as base64 string
+pub(crate) fn deserialize_base64<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<u8>, D::Error> {
+    let encoded = String::deserialize(deserializer)?;
+    STANDARD.decode(encoded).map_err(de::Error::custom)
+}
+
 impl TryFrom<&[u8]> for Chunk {
-    type Error = Error;
+    type Error = PngError;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut iter = value.iter();
+        ChunkRef::try_from(value).map(Chunk::from)
+    }
+}
+
+impl From<ChunkRef<'_>> for Chunk {
+    fn from(chunk: ChunkRef<'_>) -> Self {
+        Chunk {
+            data: chunk.data.to_vec(),
+            length: chunk.length(),
+            crc: chunk.crc,
+            chunk_type: chunk.chunk_type,
+        }
+    }
+}
+
+/// Chunk borrowing its data from the parsed buffer, so nothing is copied until it has to be modified
+#[derive(Debug, Clone)]
+pub struct ChunkRef<'a> {
+    data: &'a [u8],
+    chunk_type: ChunkType,
+    crc: u32,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Creates view of chunk whose CRC was already validated
+    pub(crate) fn new(chunk_type: ChunkType, data: &'a [u8], crc: u32) -> ChunkRef<'a> {
+        ChunkRef {
+            data,
+            chunk_type,
+            crc,
+        }
+    }
+
+    /// Length of chunk data in bytes
+    pub fn length(&self) -> u32 {
+        self.data.len() as u32
+    }
+
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+
+    pub fn data(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// CRC of chunk type and data
+    pub fn crc(&self) -> u32 {
+        self.crc
+    }
+
+    /// Returns owned copy of the chunk
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk::from(self.clone())
+    }
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses chunk at the beginning of given bytes (anything after it is ignored),
+    /// `offset` is position of these bytes in file, used in errors
+    pub fn parse(bytes: &'a [u8], offset: u64) -> Result<ChunkRef<'a>, PngError> {
+        let too_short = |field: &'static str, expected: usize| PngError::InvalidLength {
+            offset,
+            field,
+            expected: expected as u64,
+        };
+
         // Length
-        let length_buffer: Vec<u8> = iter.by_ref().take(LENGTH_FIELD_LEN).cloned().collect();
-        if length_buffer.len() < LENGTH_FIELD_LEN {
-            bail!("Length should be exactly {} bytes", LENGTH_FIELD_LEN);
+        let (length, rest) = bytes
+            .split_first_chunk::<LENGTH_FIELD_LEN>()
+            .ok_or(too_short("Length", LENGTH_FIELD_LEN))?;
+        let length = u32::from_be_bytes(*length);
+        if length > MAX_LENGTH {
+            return Err(too_short("Data", length as usize));
         }
-        let length = u32::from_be_bytes(length_buffer[0..LENGTH_FIELD_LEN].try_into()?);
 
         // Chunk type
-        let chunk_type_buffer: Vec<u8> =
-            iter.by_ref().take(CHUNK_TYPE_FIELD_LEN).cloned().collect();
-        if chunk_type_buffer.len() < CHUNK_TYPE_FIELD_LEN {
-            bail!(
-                "Chunk type should be exactly {} bytes",
-                CHUNK_TYPE_FIELD_LEN
-            );
-        }
-        let chunk_type_array: [u8; 4] = chunk_type_buffer[0..CHUNK_TYPE_FIELD_LEN].try_into()?;
-        let chunk_type = ChunkType::try_from(chunk_type_array)?;
+        let (chunk_type, rest) = rest
+            .split_first_chunk::<CHUNK_TYPE_FIELD_LEN>()
+            .ok_or(too_short("Chunk type", CHUNK_TYPE_FIELD_LEN))?;
+        let chunk_type =
+            ChunkType::try_from(*chunk_type).map_err(|_| PngError::InvalidChunkType {
+                offset: Some(offset),
+                value: chunk_type.to_vec(),
+            })?;
 
         // Data
-        let data: Vec<u8> = iter.by_ref().take(length as usize).cloned().collect();
-        if data.len() < length as usize {
-            bail!("Data should be exactly {} bytes", length);
+        if rest.len() < length as usize {
+            return Err(too_short("Data", length as usize));
         }
+        let (data, rest) = rest.split_at(length as usize);
 
         // Crc
-        let crc_buffer: Vec<u8> = iter.by_ref().take(CRC_FIELD_LEN).cloned().collect();
-        if crc_buffer.len() < CRC_FIELD_LEN {
-            bail!("Crc should be exactly {} bytes", CRC_FIELD_LEN);
-        }
-        let crc = u32::from_be_bytes(crc_buffer[0..CRC_FIELD_LEN].try_into()?);
+        let (crc, _) = rest
+            .split_first_chunk::<CRC_FIELD_LEN>()
+            .ok_or(too_short("Crc", CRC_FIELD_LEN))?;
+        let crc = u32::from_be_bytes(*crc);
 
         // Check if crc is valid
-        let valid_crc = Chunk::calculate_crc(&chunk_type, &data);
-        if crc != valid_crc {
-            bail!("Invalid crc value.");
+        let expected = Chunk::calculate_crc(&chunk_type, data);
+        if crc != expected {
+            return Err(PngError::CrcMismatch {
+                offset,
+                chunk_type: chunk_type.to_string(),
+                expected,
+                actual: crc,
+            });
         }
 
-        Ok(Chunk {
+        Ok(ChunkRef {
             data,
             chunk_type,
-            length,
             crc,
         })
     }
 }
 
+impl<'a> TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = PngError;
+
+    /// Parses chunk at the beginning of given bytes (anything after it is ignored)
+    fn try_from(value: &'a [u8]) -> Result<Self, Self::Error> {
+        ChunkRef::parse(value, 0)
+    }
+}
+
 impl Display for Chunk {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "{}: {}",
-            self.chunk_type(),
-            self.data_as_string().map_err(|_| std::fmt::Error)?
-        )
+        match self.data_as_string() {
+            Ok(data) => write!(f, "{}: {}", self.chunk_type(), data),
+            Err(_) => write!(
+                f,
+                "{}: <binary data, {} bytes>",
+                self.chunk_type(),
+                self.length()
+            ),
+        }
     }
 }
 
@@ -247,6 +420,27 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_length_over_maximum() {
+        let mut bytes = 0x8000_0000u32.to_be_bytes().to_vec();
+        bytes.extend(b"RuSt");
+        bytes.extend([0; 16]);
+
+        let error = ChunkRef::parse(&bytes, 33).unwrap_err();
+        assert!(matches!(
+            error,
+            PngError::InvalidLength {
+                offset: 33,
+                field: "Data",
+                expected: 0x8000_0000
+            }
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Chunk length 2147483648 exceeds the maximum of 2147483647 bytes (chunk at byte 33)"
+        );
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let data_length: u32 = 42;
@@ -267,4 +461,55 @@ mod tests {
 
         let _chunk_string = format!("{}", chunk);
     }
+
+    #[test]
+    fn test_chunk_ref_from_bytes() {
+        let chunk = testing_chunk();
+        let mut bytes = chunk.as_bytes();
+        bytes.extend(b"next chunk");
+
+        let chunk_ref = ChunkRef::try_from(bytes.as_slice()).unwrap();
+        assert_eq!(chunk_ref.length(), 42);
+        assert_eq!(chunk_ref.crc(), 2882656334);
+        assert_eq!(chunk_ref.data().as_ptr(), bytes[8..].as_ptr());
+        assert_eq!(chunk_ref.to_chunk().as_bytes(), chunk.as_bytes());
+
+        let error = ChunkRef::try_from(&bytes[..30]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Data should be exactly 42 bytes (chunk at byte 0)"
+        );
+        let error = ChunkRef::parse(&bytes[..52], 100).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Crc should be exactly 4 bytes (chunk at byte 100)"
+        );
+    }
+
+    #[test]
+    fn test_binary_chunk_display() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let chunk = Chunk::new(chunk_type, vec![0, 159, 146, 150]);
+        assert_eq!(chunk.to_string(), "RuSt: <binary data, 4 bytes>");
+    }
+
+    #[test]
+    fn test_chunk_serde() {
+        let chunk = testing_chunk();
+        let json = serde_json::to_value(&chunk).unwrap();
+        assert_eq!(json["chunk_type"], "RuSt");
+        assert_eq!(json["length"], 42);
+        assert_eq!(json["crc"], 2882656334u32);
+
+        let parsed: Chunk = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.as_bytes(), chunk.as_bytes());
+
+        let parsed: Chunk =
+            serde_json::from_str(r#"{"chunk_type": "ruSt", "data": "aGVsbG8="}"#).unwrap();
+        assert_eq!(parsed.data(), b"hello");
+        assert!(serde_json::from_str::<Chunk>(
+            r#"{"chunk_type": "ruSt", "crc": 1, "data": "aGVsbG8="}"#
+        )
+        .is_err());
+    }
 }
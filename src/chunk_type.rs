@@ -1,9 +1,14 @@
+//! Four letter chunk type and the properties encoded in its letter case.
+
 use std::{fmt::Display, str::FromStr};
 
-use anyhow::{bail, Error};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::error::PngError;
 
 // http://www.libpng.org/pub/png/spec/1.2/PNG-Structure.html
-#[derive(Debug, PartialEq, Eq)]
+/// Chunk type: four ascii letters
+#[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ChunkType {
     ancillary_byte: u8,
     private_byte: u8,
@@ -12,6 +17,7 @@ pub struct ChunkType {
 }
 
 impl ChunkType {
+    /// Returns the four letters as bytes
     pub fn bytes(&self) -> [u8; 4] {
         [
             self.ancillary_byte,
@@ -21,36 +27,45 @@ impl ChunkType {
         ]
     }
 
+    /// Checks that all letters are ascii letters and reserved bit is valid
     pub fn is_valid(&self) -> bool {
         self.bytes().iter().all(|byte| byte.is_ascii_alphabetic()) && self.is_reserved_bit_valid()
     }
 
+    /// Critical chunks (uppercase first letter) are required to display image
     pub fn is_critical(&self) -> bool {
         self.ancillary_byte.is_ascii_uppercase()
     }
 
+    /// Public chunks (uppercase second letter) are defined by png specification
     pub fn is_public(&self) -> bool {
         self.private_byte.is_ascii_uppercase()
     }
 
+    /// Reserved bit (case of third letter) must be uppercase
     pub fn is_reserved_bit_valid(&self) -> bool {
         self.reserved_byte.is_ascii_uppercase()
     }
 
+    /// Safe-to-copy chunks (lowercase fourth letter) can be copied by editors unaware of them
     pub fn is_safe_to_copy(&self) -> bool {
         self.safe_to_copy_byte.is_ascii_lowercase()
     }
 
+    /// Private ancillary chunks are where messages are hidden
     pub fn is_potential_message(&self) -> bool {
         self.is_valid() && !self.is_critical() && !self.is_public()
     }
 }
 
 impl TryFrom<[u8; 4]> for ChunkType {
-    type Error = Error;
+    type Error = PngError;
     fn try_from(value: [u8; 4]) -> Result<Self, Self::Error> {
         if value.iter().any(|byte| !byte.is_ascii_alphabetic()) {
-            bail!("All bytes must be valid ascii letters.");
+            return Err(PngError::InvalidChunkType {
+                offset: None,
+                value: value.to_vec(),
+            });
         }
         let ancillary_byte = value[0];
         let private_byte = value[1];
@@ -67,16 +82,17 @@ impl TryFrom<[u8; 4]> for ChunkType {
 }
 
 impl FromStr for ChunkType {
-    type Err = Error;
+    type Err = PngError;
 
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let bytes = s.as_bytes();
-        if bytes.len() == 4 {
-            let fixed_size_bytes: [u8; 4] = bytes.try_into()?;
-            ChunkType::try_from(fixed_size_bytes)
-        } else {
-            bail!("Chunk type must always have 4 ascii letters");
-        }
+        let bytes: [u8; 4] = s
+            .as_bytes()
+            .try_into()
+            .map_err(|_| PngError::InvalidChunkType {
+                offset: None,
+                value: s.as_bytes().to_vec(),
+            })?;
+        ChunkType::try_from(bytes)
     }
 }
 
@@ -87,6 +103,20 @@ impl Display for ChunkType {
     }
 }
 
+/// Serialized as the four letters
+impl Serialize for ChunkType {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for ChunkType {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        ChunkType::from_str(&value).map_err(de::Error::custom)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -184,4 +214,16 @@ mod tests {
         let _chunk_string = format!("{}", chunk_type_1);
         let _are_chunks_equal = chunk_type_1 == chunk_type_2;
     }
+
+    #[test]
+    pub fn test_chunk_type_serde() {
+        let chunk_type = ChunkType::from_str("RuSt").unwrap();
+        let json = serde_json::to_string(&chunk_type).unwrap();
+        assert_eq!(json, "\"RuSt\"");
+        assert_eq!(
+            serde_json::from_str::<ChunkType>(&json).unwrap(),
+            chunk_type
+        );
+        assert!(serde_json::from_str::<ChunkType>("\"Ru1t\"").is_err());
+    }
 }
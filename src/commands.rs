@@ -1,72 +1,1455 @@
-use std::{fs, str::FromStr};
+use std::{
+    borrow::Cow,
+    env,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    io::{self, BufWriter, Read, Write},
+    path::{Path, PathBuf},
+    process,
+    str::FromStr,
+};
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+use ed25519_dalek::{SigningKey, VerifyingKey};
+use serde::Serialize;
+use x25519_dalek::{PublicKey, StaticSecret};
 
-use crate::{
-    args::{DecodeArgs, EncodeArgs, PrintArgs, RemoveArgs},
-    chunk::Chunk,
+use edmipng::{
+    chunk::{Chunk, ChunkRef},
     chunk_type::ChunkType,
-    png::Png,
+    crypto::{self, EncryptionMethod},
+    decoder::{Image, SampleBuffer},
+    encoder,
+    error::PngError,
+    fragment,
+    ihdr::{self, Ihdr},
+    keys, lsb,
+    manifest::{self, Manifest},
+    payload::{self, FileMetadata, Payload},
+    png::{ChunkPosition, ChunkSelector, Diagnostic, Png, PngRef},
+    record::{self, ChunkRecord, Encoding},
+    signature,
     source::Source,
+    stream::{self, ChunkReader, ChunkWriter},
+    text::{self, TextChunk},
+    validation::{self, Severity, Violation},
+};
+
+use crate::{
+    args::{
+        Carrier, CreateArgs, DecodeArgs, DumpArgs, DumpMode, EncodeArgs, ExportArgs, ExtractArgs,
+        InspectArgs, KeygenArgs, ManifestFormat, OutputFormat, PackArgs, PrintArgs, RemoveArgs,
+        RepairArgs, SelectorArgs, TextGetArgs, TextListArgs, TextRemoveArgs, TextSetArgs,
+        UpdateArgs, ValidateArgs, VerifyArgs,
+    },
+    output,
 };
 
+const PASSPHRASE_ENV_KEY: &str = "EDMIPNG_PASSPHRASE";
+
 pub fn encode(arguments: EncodeArgs) -> Result<()> {
     let source = Source::from_str(&arguments.source)?;
-    let mut png = Png::try_from(&source)?;
-
     let chunk_type =
-        ChunkType::from_str(&arguments.chunk_type).context("Coulnd't parse chunk type.")?;
-    let chunk = Chunk::new(chunk_type, arguments.message.into());
-    png.append_chunk(chunk);
+        ChunkType::from_str(&arguments.chunk_type).context("Couldn't parse chunk type.")?;
+    if arguments.carrier == Carrier::Lsb
+        && (arguments.fragment_size.is_some()
+            || arguments.sign.is_some()
+            || arguments.before.is_some()
+            || arguments.after.is_some()
+            || arguments.index.is_some())
+    {
+        bail!(
+            "--fragment-size, --sign, --before, --after and --index can't be used with LSB carrier"
+        );
+    }
+    check_stdin_use(&source, arguments.stdin)?;
+    let output = output_destination(
+        arguments.output.or(arguments.output_file),
+        arguments.stdout,
+        &source,
+    )?;
+    let payload = read_payload(
+        arguments.message,
+        arguments.file.as_deref(),
+        arguments.stdin,
+        arguments.mime_type,
+    )?;
+    let data = encode_message(
+        &chunk_type,
+        payload,
+        arguments.compress,
+        arguments.encrypt,
+        &arguments.recipient,
+    )?;
+    if arguments.carrier == Carrier::Lsb {
+        let mut png = Png::try_from(&source)?;
+        if arguments.strip_trailing_data {
+            png.strip_trailing_data();
+        }
+        lsb::embed(&mut png, &data)?;
+        return write_output(output, &png);
+    }
+    let fragments: Vec<Chunk> = match arguments.fragment_size {
+        Some(fragment_size) if data.len() > fragment_size => fragment::split(&data, fragment_size)?
+            .into_iter()
+            .map(|fragment| Chunk::try_new(chunk_type.clone(), fragment))
+            .collect::<Result<Vec<Chunk>>>()?,
+        _ => vec![Chunk::try_new(chunk_type, data)?],
+    };
+    let signing_key = arguments
+        .sign
+        .as_ref()
+        .map(|path| keys::read_key(path, keys::ED25519_SECRET_KEY_LABEL))
+        .transpose()?
+        .map(|key| SigningKey::from_bytes(&key));
+    let mut chunks = Vec::new();
+    for chunk in fragments {
+        let signature_chunk = match &signing_key {
+            Some(key) => Some(signature::sign_chunk(&chunk, key)?),
+            None => None,
+        };
+        chunks.push(chunk);
+        chunks.extend(signature_chunk);
+    }
 
-    let output_path = arguments
-        .output_file
-        .unwrap_or(source.get_output_file_path()?);
-    fs::write(output_path, png.as_bytes()).context("Couldn't write to png file.")?;
+    // Last chunk with given type is only known after reading the whole file, so it needs an extra pass
+    // (standard input can't be read twice and url would be downloaded twice, so then it's kept in memory)
+    let position = chunk_position(&arguments.before, &arguments.after, arguments.index)?;
+    let buffered_source = match (&source, &position) {
+        (Source::Stdin | Source::Url(_), ChunkPosition::After(_)) => Some(source.read_bytes()?),
+        _ => None,
+    };
+    let open = || -> Result<Box<dyn Read + '_>> {
+        match &buffered_source {
+            Some(bytes) => Ok(Box::new(bytes.as_slice())),
+            None => source.open(),
+        }
+    };
+    let position = match position {
+        ChunkPosition::After(chunk_type) => {
+            let mut reader = ChunkReader::new(open()?)?;
+            ChunkPosition::Index(stream::index_after(&mut reader, &chunk_type)?)
+        }
+        position => position,
+    };
+    stream_output(output.as_deref(), |writer| {
+        let mut reader = ChunkReader::new(open()?)?;
+        stream::insert_chunks(&mut reader, writer, chunks, &position)
+            .context("Couldn't insert chunk.")?;
+        if !arguments.strip_trailing_data {
+            reader.copy_trailing_data(writer)?;
+        }
+        Ok(())
+    })
+}
+
+/// Turns payload into chunk data, compressing and encrypting it if requested
+fn encode_message(
+    chunk_type: &ChunkType,
+    payload: Payload,
+    compress: bool,
+    encrypt: bool,
+    recipients: &[PathBuf],
+) -> Result<Vec<u8>> {
+    let message = if compress || payload.metadata.is_some() {
+        payload::wrap(&payload, compress)?
+    } else {
+        payload.content
+    };
+    if encrypt {
+        let passphrase = read_passphrase(true)?;
+        return crypto::encrypt_with_passphrase(&message, &passphrase, &chunk_type.bytes());
+    }
+    if !recipients.is_empty() {
+        let recipients = recipients
+            .iter()
+            .map(|path| keys::read_key(path, keys::X25519_PUBLIC_KEY_LABEL).map(PublicKey::from))
+            .collect::<Result<Vec<PublicKey>>>()?;
+        return crypto::encrypt_for_recipients(&message, &recipients, &chunk_type.bytes());
+    }
+    Ok(message)
+}
+
+/// Refuses to read message from standard input when png file is read from there too
+fn check_stdin_use(source: &Source, stdin: bool) -> Result<()> {
+    if stdin && matches!(source, Source::Stdin) {
+        bail!("Standard input can't be used both as the source and the message");
+    }
+    Ok(())
+}
+
+/// Picks file to which edited png is written, `None` meaning standard output
+/// (chosen with `-` or --stdout, and used by default when png is read from standard input)
+fn output_destination(
+    output_file: Option<PathBuf>,
+    stdout: bool,
+    source: &Source,
+) -> Result<Option<PathBuf>> {
+    match output_file {
+        _ if stdout => Ok(None),
+        Some(path) if path.as_os_str() == "-" => Ok(None),
+        Some(path) => Ok(Some(path)),
+        None if matches!(source, Source::Stdin) => Ok(None),
+        None => source.get_output_file_path().map(Some),
+    }
+}
+
+fn write_output(output: Option<PathBuf>, png: &Png) -> Result<()> {
+    let result = match output {
+        Some(output_path) => fs::write(output_path, png.as_bytes()),
+        None => io::stdout().lock().write_all(&png.as_bytes()),
+    };
+    result
+        .map_err(PngError::Io)
+        .context("Couldn't write to png file.")?;
 
     Ok(())
 }
 
+/// Streams png file to given file (see `stream_to_file`) or to standard output.
+/// Output for standard output is buffered, so that nothing is written if editing fails.
+fn stream_output<F>(output_path: Option<&Path>, write: F) -> Result<()>
+where
+    F: FnOnce(&mut ChunkWriter<&mut dyn Write>) -> Result<()>,
+{
+    match output_path {
+        Some(output_path) => stream_to_file(output_path, write),
+        None => {
+            let mut buffer = Vec::new();
+            let mut writer = ChunkWriter::new(&mut buffer as &mut dyn Write)?;
+            write(&mut writer)?;
+            writer.finish()?;
+            io::stdout()
+                .lock()
+                .write_all(&buffer)
+                .map_err(PngError::Io)
+                .context("Couldn't write png file.")
+        }
+    }
+}
+
+/// Writes png file through temporary file, which replaces output file only once everything succeeded,
+/// so that output can be the same file that is being read.
+/// Temporary file is named after the process and never overwrites existing file.
+fn stream_to_file<F>(output_path: &Path, write: F) -> Result<()>
+where
+    F: FnOnce(&mut ChunkWriter<&mut dyn Write>) -> Result<()>,
+{
+    let mut temp_path = output_path.as_os_str().to_owned();
+    temp_path.push(format!(".{}.tmp", process::id()));
+    let temp_path = PathBuf::from(temp_path);
+
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&temp_path)
+        .map_err(PngError::Io)
+        .context("Couldn't write to png file.")?;
+    let mut file = BufWriter::new(file);
+    let result = ChunkWriter::new(&mut file as &mut dyn Write).and_then(|mut writer| {
+        write(&mut writer)?;
+        writer.finish()?;
+        Ok(())
+    });
+    drop(file);
+    let result = result.and_then(|_| {
+        fs::rename(&temp_path, output_path)
+            .map_err(PngError::Io)
+            .context("Couldn't write to png file.")
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp_path);
+    }
+    result
+}
+
+fn read_payload(
+    message: Option<String>,
+    file: Option<&Path>,
+    stdin: bool,
+    mime_type: Option<String>,
+) -> Result<Payload> {
+    if let Some(path) = file {
+        let content = fs::read(path)
+            .map_err(PngError::Io)
+            .context("Couldn't read embedded file.")?;
+        let file_name = path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        let mime_type = mime_type.unwrap_or_else(|| {
+            mime_guess::from_path(path)
+                .first_or_octet_stream()
+                .to_string()
+        });
+        return Ok(Payload {
+            content,
+            metadata: Some(FileMetadata {
+                file_name,
+                mime_type,
+            }),
+        });
+    }
+    if stdin {
+        let mut content = Vec::new();
+        io::stdin()
+            .read_to_end(&mut content)
+            .context("Couldn't read standard input.")?;
+        let mime_type =
+            mime_type.unwrap_or_else(|| mime_guess::mime::APPLICATION_OCTET_STREAM.to_string());
+        return Ok(Payload {
+            content,
+            metadata: Some(FileMetadata {
+                file_name: String::new(),
+                mime_type,
+            }),
+        });
+    }
+    match message {
+        Some(message) => Ok(Payload {
+            content: message.into_bytes(),
+            metadata: None,
+        }),
+        None => bail!("Message, --file or --stdin must be provided"),
+    }
+}
+
+fn chunk_position(
+    before: &Option<String>,
+    after: &Option<String>,
+    index: Option<usize>,
+) -> Result<ChunkPosition> {
+    let position = match (before, after, index) {
+        (Some(before), _, _) => ChunkPosition::Before(
+            ChunkType::from_str(before).context("Couldn't parse --before chunk type.")?,
+        ),
+        (_, Some(after), _) => ChunkPosition::After(
+            ChunkType::from_str(after).context("Couldn't parse --after chunk type.")?,
+        ),
+        (_, _, Some(index)) => ChunkPosition::Index(index),
+        _ => ChunkPosition::BeforeIend,
+    };
+    Ok(position)
+}
+
 pub fn decode(arguments: DecodeArgs) -> Result<()> {
     let source = Source::from_str(&arguments.source)?;
-    let png = Png::try_from(&source)?;
+    let selector = chunk_selector(&arguments.selector);
+    if arguments.carrier == Carrier::Lsb && selector != ChunkSelector::First {
+        bail!("--all, --index and --nth can't be used with LSB carrier");
+    }
+    if arguments.selector.all && arguments.output.is_some() {
+        bail!("--all can't be used with --output, as every message would overwrite the file");
+    }
+    let png = if arguments.lenient {
+        let (png, diagnostics) = Png::parse_lenient(&source.read_bytes()?)?;
+        for diagnostic in diagnostics {
+            eprintln!("Warning: {}", diagnostic);
+        }
+        png
+    } else {
+        Png::try_from(&source)?
+    };
 
-    let chunk = png.chunk_by_type(&arguments.chunk_type);
+    let messages = match arguments.carrier {
+        Carrier::Lsb => {
+            let chunk_type =
+                ChunkType::from_str(&arguments.chunk_type).context("Couldn't parse chunk type.")?;
+            vec![(None, chunk_type, lsb::extract(&png)?)]
+        }
+        Carrier::Chunk => {
+            let indices = png.select_chunks(&arguments.chunk_type, selector);
+            if indices.is_empty() && arguments.format == OutputFormat::Text {
+                println!(
+                    "Chunk with given type ({}) doesn't exist",
+                    arguments.chunk_type
+                );
+                return Ok(());
+            }
+            // Fragments are reassembled once, every sequence gives one message
+            let mut sequences: Vec<Option<fragment::Sequence>> = match indices
+                .iter()
+                .find(|&&index| fragment::is_fragment(png.chunks()[index].data()))
+            {
+                Some(&index) => fragment::reassemble(&png, png.chunks()[index].chunk_type())?
+                    .into_iter()
+                    .map(Some)
+                    .collect(),
+                None => Vec::new(),
+            };
+            let mut messages = Vec::new();
+            let mut failures = Vec::new();
+            for index in indices {
+                let chunk = &png.chunks()[index];
+                if !fragment::is_fragment(chunk.data()) {
+                    messages.push((
+                        Some(index),
+                        chunk.chunk_type().clone(),
+                        chunk.data().to_vec(),
+                    ));
+                    continue;
+                }
+                let Some(sequence) = sequences
+                    .iter_mut()
+                    .find(|sequence| {
+                        sequence
+                            .as_ref()
+                            .is_some_and(|sequence| sequence.chunks.contains(&index))
+                    })
+                    .and_then(Option::take)
+                else {
+                    // Sequence of this fragment was already decoded
+                    continue;
+                };
+                match sequence.result {
+                    Ok(reassembled) => {
+                        for warning in reassembled.warnings {
+                            eprintln!(
+                                "Warning: sequence {:08x}: {}",
+                                sequence.sequence_id, warning
+                            );
+                        }
+                        messages.push((
+                            Some(sequence.chunks[0]),
+                            chunk.chunk_type().clone(),
+                            reassembled.data,
+                        ));
+                    }
+                    Err(err) => failures.push(err.context(format!(
+                        "Couldn't reassemble fragments of sequence {:08x}",
+                        sequence.sequence_id
+                    ))),
+                }
+            }
+            // Broken sequence is only skipped when other messages are decoded with --all
+            if !failures.is_empty() && (selector != ChunkSelector::All || messages.is_empty()) {
+                return Err(failures.remove(0));
+            }
+            for failure in failures {
+                eprintln!("Warning: {:#}", failure);
+            }
+            messages
+        }
+    };
 
-    match chunk {
-        Some(chunk) => {
-            println!("{}", chunk);
+    let offsets = png.chunk_offsets();
+    let mut records = Vec::new();
+    for (index, chunk_type, data) in messages {
+        let data = if crypto::is_encrypted(&data) {
+            decrypt_message(&data, &chunk_type, arguments.identity.as_deref())?
+        } else {
+            data
+        };
+        let payload = payload::unwrap(&data, arguments.max_size)?;
+        if let Some(path) = &arguments.output {
+            fs::write(path, payload.content)
+                .map_err(PngError::Io)
+                .context("Couldn't write decoded data")?;
+        } else if arguments.raw {
+            io::stdout()
+                .write_all(&payload.content)
+                .context("Couldn't write decoded data")?;
+        } else if arguments.format == OutputFormat::Text {
+            print_payload(&chunk_type, payload)?;
+        } else {
+            let chunk = index.map(|index| {
+                ChunkRecord::new(
+                    index,
+                    offsets[index] as u64,
+                    &png.chunks()[index].as_chunk_ref(),
+                )
+            });
+            records.push(MessageRecord::new(chunk, chunk_type, payload));
         }
-        None => {
-            println!(
-                "Chunk with given type ({}) doesn't exist",
-                arguments.chunk_type
+    }
+    if arguments.output.is_some() || arguments.raw {
+        return Ok(());
+    }
+    match arguments.format {
+        OutputFormat::Text => Ok(()),
+        OutputFormat::Table => {
+            let rows: Vec<Vec<String>> = records
+                .iter()
+                .map(|record| {
+                    let chunk = record.chunk.as_ref();
+                    let field = |value: Option<String>| value.unwrap_or_default();
+                    vec![
+                        field(chunk.map(|chunk| chunk.index.to_string())),
+                        field(chunk.map(|chunk| chunk.offset.to_string())),
+                        field(
+                            chunk
+                                .map(|chunk| &chunk.chunk_type)
+                                .or(record.chunk_type.as_ref())
+                                .map(ChunkType::to_string),
+                        ),
+                        field(chunk.map(|chunk| chunk.flags().join(","))),
+                        field(chunk.map(|chunk| chunk.length.to_string())),
+                        field(chunk.map(|chunk| format!("{:08x}", chunk.crc))),
+                        field(record.file_name.clone()),
+                        record.encoding.name().to_string(),
+                        record.message.clone(),
+                    ]
+                })
+                .collect();
+            output::print_table(
+                &[
+                    "INDEX", "OFFSET", "TYPE", "FLAGS", "LENGTH", "CRC", "FILE", "ENCODING",
+                    "MESSAGE",
+                ],
+                &rows,
             );
+            Ok(())
+        }
+        format => output::print_document(&records, format),
+    }
+}
+
+/// Decoded message, as printed in machine-readable formats
+#[derive(Serialize)]
+struct MessageRecord {
+    /// Chunk holding the message (first fragment of fragmented one), missing for LSB carrier
+    #[serde(flatten)]
+    chunk: Option<ChunkRecord>,
+    /// Chunk type of message hidden in pixels, for chunk carrier it is part of `chunk`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    chunk_type: Option<ChunkType>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    file_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    mime_type: Option<String>,
+    /// How `message` is written
+    encoding: Encoding,
+    message: String,
+}
+
+impl MessageRecord {
+    fn new(chunk: Option<ChunkRecord>, chunk_type: ChunkType, payload: Payload) -> MessageRecord {
+        let (encoding, message) = record::encode_payload(&payload.content);
+        let (file_name, mime_type) = match payload.metadata {
+            Some(metadata) => (Some(metadata.file_name), Some(metadata.mime_type)),
+            None => (None, None),
+        };
+        MessageRecord {
+            chunk_type: chunk.is_none().then_some(chunk_type),
+            chunk,
+            file_name,
+            mime_type,
+            encoding,
+            message,
         }
     }
+}
+
+fn chunk_selector(arguments: &SelectorArgs) -> ChunkSelector {
+    match (arguments.all, arguments.index, arguments.nth) {
+        (true, _, _) => ChunkSelector::All,
+        (_, Some(index), _) => ChunkSelector::Index(index),
+        (_, _, Some(nth)) => ChunkSelector::Nth(nth),
+        _ => ChunkSelector::First,
+    }
+}
 
+fn print_payload(chunk_type: &ChunkType, payload: Payload) -> Result<()> {
+    match payload.metadata {
+        Some(metadata) => {
+            let file_name = match metadata.file_name.as_str() {
+                "" => "<unnamed>",
+                file_name => file_name,
+            };
+            println!(
+                "{}: file {} ({}, {} bytes), use --output or --raw to extract it",
+                chunk_type,
+                file_name,
+                metadata.mime_type,
+                payload.content.len()
+            );
+        }
+        None => {
+            let message = String::from_utf8(payload.content)
+                .context("Message is not valid utf-8, use --output or --raw to extract it")?;
+            println!("{}: {}", chunk_type, message);
+        }
+    }
     Ok(())
 }
 
+fn decrypt_message(
+    data: &[u8],
+    chunk_type: &ChunkType,
+    identity: Option<&Path>,
+) -> Result<Vec<u8>> {
+    let associated_data = chunk_type.bytes();
+    match crypto::encryption_method(data)? {
+        EncryptionMethod::Passphrase => {
+            let passphrase = read_passphrase(false)?;
+            crypto::decrypt_with_passphrase(data, &passphrase, &associated_data)
+        }
+        EncryptionMethod::Recipients => {
+            let identity = identity.context(
+                "Message is encrypted to recipients, provide secret key with --identity",
+            )?;
+            let secret =
+                StaticSecret::from(keys::read_key(identity, keys::X25519_SECRET_KEY_LABEL)?);
+            crypto::decrypt_with_identity(data, &secret, &associated_data)
+        }
+    }
+}
+
+/// Reads passphrase from environment variable, or if it's not set prompts user for it
+fn read_passphrase(confirm: bool) -> Result<String> {
+    if let Ok(passphrase) = env::var(PASSPHRASE_ENV_KEY) {
+        return Ok(passphrase);
+    }
+    let passphrase =
+        rpassword::prompt_password("Passphrase: ").context("Couldn't read passphrase")?;
+    if confirm {
+        let repeated = rpassword::prompt_password("Repeat passphrase: ")
+            .context("Couldn't read passphrase")?;
+        if passphrase != repeated {
+            bail!("Passphrases don't match");
+        }
+    }
+    Ok(passphrase)
+}
+
 pub fn remove(arguments: RemoveArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let chunk_type = ChunkType::from_str(&arguments.chunk_type).context("Couldn't remove chunk")?;
+
+    let output = output_destination(
+        arguments.output.or(arguments.output_file),
+        arguments.stdout,
+        &source,
+    )?;
+    let selector = chunk_selector(&arguments.selector);
+    stream_output(output.as_deref(), |writer| {
+        let mut reader = ChunkReader::new(source.open()?)?;
+        stream::remove_chunks(&mut reader, writer, &chunk_type, selector)
+            .context("Couldn't remove chunk")?;
+        if !arguments.strip_trailing_data {
+            reader.copy_trailing_data(writer)?;
+        }
+        Ok(())
+    })
+}
+
+pub fn update(arguments: UpdateArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let chunk_type =
+        ChunkType::from_str(&arguments.chunk_type).context("Couldn't parse chunk type.")?;
+    check_stdin_use(&source, arguments.stdin)?;
+    let mut png = Png::try_from(&source)?;
+
+    let indices = png.select_chunks(&arguments.chunk_type, chunk_selector(&arguments.selector));
+    if indices.is_empty() {
+        return Err(PngError::MissingChunk {
+            chunk_type: chunk_type.to_string(),
+            offset: None,
+        })
+        .context("Couldn't update chunk");
+    }
+    if indices
+        .iter()
+        .any(|&index| fragment::is_fragment(png.chunks()[index].data()))
+    {
+        bail!("Chunk is a fragment of split message, remove all its fragments and encode it again");
+    }
+
+    let payload = read_payload(
+        arguments.message,
+        arguments.file.as_deref(),
+        arguments.stdin,
+        arguments.mime_type,
+    )?;
+    let data = encode_message(
+        &chunk_type,
+        payload,
+        arguments.compress,
+        arguments.encrypt,
+        &arguments.recipient,
+    )?;
+    let signing_key = arguments
+        .sign
+        .as_ref()
+        .map(|path| keys::read_key(path, keys::ED25519_SECRET_KEY_LABEL))
+        .transpose()?
+        .map(|key| SigningKey::from_bytes(&key));
+    // Going from the back keeps indices valid when stale signatures are removed
+    for index in indices.into_iter().rev() {
+        let chunk = Chunk::try_new(chunk_type.clone(), data.clone())?;
+        let signature_chunk = match &signing_key {
+            Some(key) => Some(signature::sign_chunk(&chunk, key)?),
+            None => None,
+        };
+        let signed = png
+            .chunks()
+            .get(index + 1)
+            .is_some_and(|next| next.chunk_type().to_string() == signature::SIGNATURE_CHUNK_TYPE);
+        png.replace_chunk(index, chunk)
+            .context("Couldn't update chunk")?;
+        match (signed, signature_chunk) {
+            (true, Some(signature_chunk)) => {
+                png.replace_chunk(index + 1, signature_chunk)?;
+            }
+            (false, Some(signature_chunk)) => {
+                png.insert_chunk(signature_chunk, ChunkPosition::Index(index + 1))?;
+            }
+            (true, None) => {
+                png.remove_chunks(
+                    signature::SIGNATURE_CHUNK_TYPE,
+                    ChunkSelector::Index(index + 1),
+                )?;
+                eprintln!(
+                    "Warning: signature of chunk #{} no longer matched its content and was removed (use --sign to sign it again)",
+                    index
+                );
+            }
+            (false, None) => {}
+        }
+    }
+
+    write_output(
+        output_destination(
+            arguments.output.or(arguments.output_file),
+            arguments.stdout,
+            &source,
+        )?,
+        &png,
+    )
+}
+
+pub fn print(arguments: PrintArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let bytes = source.read_bytes()?;
+    let png = PngRef::parse(&bytes)?;
+    if arguments.format != OutputFormat::Text {
+        let chunks: Vec<ChunkRecord> = png
+            .chunks()
+            .iter()
+            .zip(png.chunk_offsets())
+            .enumerate()
+            .filter(|(_, (chunk, _))| chunk.chunk_type().is_potential_message())
+            .map(|(index, (chunk, offset))| ChunkRecord::with_payload(index, offset as u64, chunk))
+            .collect();
+        return print_chunk_records(chunks, png.trailing_data().len(), arguments.format, true);
+    }
+    println!("Special chunk types inside file (private + ancillary):");
+    println!("{}", png);
+    if !png.trailing_data().is_empty() {
+        println!(
+            "File contains {} bytes of data after IEND chunk",
+            png.trailing_data().len()
+        );
+    }
+    Ok(())
+}
+
+/// Chunks of file, as printed in machine-readable formats
+#[derive(Serialize)]
+struct ChunkListing {
+    chunks: Vec<ChunkRecord>,
+    /// Number of bytes stored after IEND chunk
+    trailing_data: usize,
+}
+
+/// Prints chunk descriptions, in table format with their data or with description and summary of their content
+fn print_chunk_records(
+    chunks: Vec<ChunkRecord>,
+    trailing_data: usize,
+    format: OutputFormat,
+    with_payload: bool,
+) -> Result<()> {
+    if format != OutputFormat::Table {
+        return output::print_document(
+            &ChunkListing {
+                chunks,
+                trailing_data,
+            },
+            format,
+        );
+    }
+    let rows: Vec<Vec<String>> = chunks
+        .iter()
+        .map(|record| {
+            let mut row = vec![
+                record.index.to_string(),
+                record.offset.to_string(),
+                record.chunk_type.to_string(),
+                record.flags().join(","),
+                record.length.to_string(),
+                format!("{:08x}", record.crc),
+            ];
+            if with_payload {
+                row.push(
+                    record
+                        .encoding
+                        .map(|encoding| encoding.name())
+                        .unwrap_or_default()
+                        .to_string(),
+                );
+                row.push(record.payload.clone().unwrap_or_default());
+            } else {
+                row.push(record.description.unwrap_or_default().to_string());
+                row.push(record.summary.clone().unwrap_or_default());
+            }
+            row
+        })
+        .collect();
+    let mut headers = vec!["INDEX", "OFFSET", "TYPE", "FLAGS", "LENGTH", "CRC"];
+    if with_payload {
+        headers.extend(["ENCODING", "PAYLOAD"]);
+    } else {
+        headers.extend(["DESCRIPTION", "SUMMARY"]);
+    }
+    output::print_table(&headers, &rows);
+    Ok(())
+}
+
+pub fn inspect(arguments: InspectArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let bytes = source.read_bytes()?;
+    let png = PngRef::parse(&bytes)?;
+    let chunks = record::chunk_records(&png, |_| false);
+    let trailing_data = png.trailing_data().len();
+    if arguments.format != OutputFormat::Text {
+        return print_chunk_records(chunks, trailing_data, arguments.format, false);
+    }
+
+    for record in chunks {
+        println!(
+            "#{} {} at byte {}: {} bytes, CRC {:#010x} ({})",
+            record.index,
+            record.chunk_type,
+            record.offset,
+            record.length,
+            record.crc,
+            record.flags().join(", ")
+        );
+        let details = match (record.description, record.summary) {
+            (Some(description), Some(summary)) => format!("{}: {}", description, summary),
+            (Some(description), None) => description.to_string(),
+            (None, Some(summary)) => summary,
+            (None, None) => String::from("Unknown chunk type"),
+        };
+        println!("    {}", details);
+    }
+    if trailing_data > 0 {
+        println!("{} bytes of data after IEND chunk", trailing_data);
+    }
+    Ok(())
+}
+
+pub fn dump(arguments: DumpArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let bytes = source.read_bytes()?;
+    let png = PngRef::parse(&bytes)?;
+
+    let indices = match arguments.chunk.parse::<usize>() {
+        Ok(index) if index < png.chunks().len() => vec![index],
+        Ok(index) => bail!(
+            "Index {} is out of range (file has {} chunks)",
+            index,
+            png.chunks().len()
+        ),
+        Err(_) => {
+            let chunk_type = ChunkType::from_str(&arguments.chunk)
+                .context("Chunk should be given as chunk type or index")?;
+            let selector = if arguments.all {
+                ChunkSelector::All
+            } else {
+                ChunkSelector::First
+            };
+            let indices = png.select_chunks(&arguments.chunk, selector);
+            if indices.is_empty() {
+                return Err(PngError::MissingChunk {
+                    chunk_type: chunk_type.to_string(),
+                    offset: None,
+                }
+                .into());
+            }
+            indices
+        }
+    };
+
+    let offsets = png.chunk_offsets();
+    let mut stdout = io::stdout();
+    for index in indices {
+        let (bytes, offset) = chunk_bytes(&png.chunks()[index], offsets[index], arguments.whole);
+        match arguments.mode {
+            DumpMode::Hex => {
+                println!(
+                    "#{} {} ({} bytes):",
+                    index,
+                    png.chunks()[index].chunk_type(),
+                    bytes.len()
+                );
+                print!("{}", output::hexdump(&bytes, offset as u64));
+            }
+            DumpMode::Base64 => println!("{}", BASE64.encode(&bytes)),
+            DumpMode::Raw => stdout
+                .write_all(&bytes)
+                .context("Couldn't write chunk bytes")?,
+        }
+    }
+    Ok(())
+}
+
+/// Returns data of chunk or whole serialized chunk, together with their offset in file
+fn chunk_bytes<'a>(chunk: &ChunkRef<'a>, offset: usize, whole: bool) -> (Cow<'a, [u8]>, usize) {
+    if whole {
+        (Cow::Owned(chunk.to_chunk().as_bytes()), offset)
+    } else {
+        // Data starts after length and chunk type
+        (Cow::Borrowed(chunk.data()), offset + 8)
+    }
+}
+
+pub fn extract(arguments: ExtractArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let bytes = source.read_bytes()?;
+    let png = PngRef::parse(&bytes)?;
+
+    let indices = match &arguments.chunk_type {
+        Some(chunk_type) => {
+            let chunk_type =
+                ChunkType::from_str(chunk_type).context("Couldn't parse chunk type.")?;
+            let indices =
+                png.select_chunks(&chunk_type.to_string(), chunk_selector(&arguments.selector));
+            if indices.is_empty() {
+                return Err(PngError::MissingChunk {
+                    chunk_type: chunk_type.to_string(),
+                    offset: None,
+                }
+                .into());
+            }
+            indices
+        }
+        None if arguments.selector.index.is_some() || arguments.selector.nth.is_some() => {
+            bail!("--index and --nth can only be used together with chunk type")
+        }
+        None => (0..png.chunks().len()).collect(),
+    };
+
+    fs::create_dir_all(&arguments.dir)
+        .map_err(PngError::Io)
+        .context("Couldn't create output directory")?;
+    let offsets = png.chunk_offsets();
+    for index in indices {
+        let chunk = &png.chunks()[index];
+        let (bytes, _) = chunk_bytes(chunk, offsets[index], arguments.whole);
+        let path = arguments
+            .dir
+            .join(chunk_file_name(index, chunk.chunk_type(), arguments.whole));
+        fs::write(&path, bytes)
+            .map_err(PngError::Io)
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+        println!("{}", path.display());
+    }
+    Ok(())
+}
+
+/// Name of file with extracted chunk, e.g. `003-ruSt.bin` (`.chunk` for whole serialized chunk)
+fn chunk_file_name(index: usize, chunk_type: &ChunkType, whole: bool) -> PathBuf {
+    let name = PathBuf::from(manifest::chunk_file_name(index, chunk_type));
+    if whole {
+        name.with_extension("chunk")
+    } else {
+        name
+    }
+}
+
+pub fn export(arguments: ExportArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let bytes = source.read_bytes()?;
+    let png = PngRef::parse(&bytes)?;
+    let manifest = Manifest::from_png(&png);
+
+    fs::create_dir_all(&arguments.dir)
+        .map_err(PngError::Io)
+        .context("Couldn't create output directory")?;
+    let files = manifest
+        .chunks
+        .iter()
+        .zip(png.chunks())
+        .map(|(entry, chunk)| (&entry.file, chunk.data()))
+        .chain(
+            manifest
+                .trailing_data
+                .iter()
+                .map(|file| (file, png.trailing_data())),
+        );
+    for (file, data) in files {
+        let path = arguments.dir.join(file);
+        fs::write(&path, data)
+            .map_err(PngError::Io)
+            .with_context(|| format!("Couldn't write {}", path.display()))?;
+    }
+
+    let (manifest_text, manifest_name) = match arguments.manifest_format {
+        ManifestFormat::Json => (manifest.to_json()?, "manifest.json"),
+        ManifestFormat::Toml => (manifest.to_toml()?, "manifest.toml"),
+    };
+    let manifest_path = arguments.dir.join(manifest_name);
+    fs::write(&manifest_path, manifest_text)
+        .map_err(PngError::Io)
+        .context("Couldn't write manifest")?;
+    println!(
+        "Exported {} chunk(s), manifest written to {}",
+        manifest.chunks.len(),
+        manifest_path.display()
+    );
+
+    Ok(())
+}
+
+pub fn pack(arguments: PackArgs) -> Result<()> {
+    let manifest = Manifest::from_file(&arguments.manifest)?;
+    // Data files are looked up next to the manifest
+    let dir = arguments
+        .manifest
+        .parent()
+        .filter(|dir| !dir.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    let png = manifest.pack(dir)?;
+
+    // Edited files are written anyway, but structure problems shouldn't go unnoticed
+    for violation in validation::validate(&png) {
+        eprintln!("{}", violation);
+    }
+    fs::write(arguments.output_file, png.as_bytes())
+        .map_err(PngError::Io)
+        .context("Couldn't write to png file.")?;
+
+    Ok(())
+}
+
+pub fn keygen(arguments: KeygenArgs) -> Result<()> {
+    let mut public_path = OsString::from(arguments.output_file.as_os_str());
+    public_path.push(".pub");
+
+    let (secret, public, secret_label, public_label) = if arguments.signing {
+        let mut seed = [0u8; 32];
+        OsRng.fill_bytes(&mut seed);
+        let secret = SigningKey::from_bytes(&seed);
+        (
+            secret.to_bytes(),
+            secret.verifying_key().to_bytes(),
+            keys::ED25519_SECRET_KEY_LABEL,
+            keys::ED25519_PUBLIC_KEY_LABEL,
+        )
+    } else {
+        let secret = StaticSecret::random_from_rng(OsRng);
+        (
+            secret.to_bytes(),
+            PublicKey::from(&secret).to_bytes(),
+            keys::X25519_SECRET_KEY_LABEL,
+            keys::X25519_PUBLIC_KEY_LABEL,
+        )
+    };
+
+    keys::write_key(&arguments.output_file, secret_label, &secret, true)?;
+    keys::write_key(&public_path, public_label, &public, false)?;
+
+    println!("Secret key saved to {}", arguments.output_file.display());
+    println!("Public key saved to {}", Path::new(&public_path).display());
+    Ok(())
+}
+
+pub fn verify(arguments: VerifyArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let png = Png::try_from(&source)?;
+
+    let trusted = arguments
+        .trusted
+        .iter()
+        .map(|path| {
+            let key = keys::read_key(path, keys::ED25519_PUBLIC_KEY_LABEL)?;
+            let key = VerifyingKey::from_bytes(&key).context("Invalid public signing key")?;
+            Ok((key, path.display().to_string()))
+        })
+        .collect::<Result<Vec<(VerifyingKey, String)>>>()?;
+
+    let checks = signature::verify_chunks(&png);
+    if checks.is_empty() {
+        println!("There are no signed chunks inside file");
+        return Ok(());
+    }
+
+    let mut failed = 0;
+    for check in checks {
+        let trusted_name = check.signer.and_then(|signer| {
+            trusted
+                .iter()
+                .find(|(key, _)| *key == signer)
+                .map(|(_, name)| name.as_str())
+        });
+        let signer = match (trusted_name, check.signer) {
+            (Some(name), _) => name.to_string(),
+            (None, Some(signer)) => format!("untrusted key {}", keys::to_hex(signer.as_bytes())),
+            (None, None) => String::from("unknown key"),
+        };
+        match check.result {
+            Err(err) if check.orphaned => {
+                failed += 1;
+                println!(
+                    "#{} {}: orphaned signature of {} chunk by {} ({})",
+                    check.index,
+                    signature::SIGNATURE_CHUNK_TYPE,
+                    check.chunk_type,
+                    signer,
+                    err
+                );
+            }
+            Ok(()) => {
+                if trusted_name.is_none() {
+                    failed += 1;
+                }
+                println!(
+                    "#{} {}: valid signature by {}",
+                    check.index, check.chunk_type, signer
+                );
+            }
+            Err(err) => {
+                failed += 1;
+                println!(
+                    "#{} {}: invalid signature by {} ({})",
+                    check.index, check.chunk_type, signer, err
+                );
+            }
+        }
+    }
+
+    if failed > 0 {
+        bail!("{} signature(s) couldn't be verified", failed);
+    }
+    Ok(())
+}
+
+pub fn text_list(arguments: TextListArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let png = Png::try_from(&source)?;
+    for text in png.text_chunks() {
+        println!(
+            "{} ({}): {}",
+            text.keyword(),
+            text.chunk_type(),
+            text.text()
+        );
+    }
+    Ok(())
+}
+
+pub fn text_get(arguments: TextGetArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let png = Png::try_from(&source)?;
+    match png.text_by_keyword(&arguments.keyword) {
+        Some(text) => println!("{}", text.text()),
+        None => println!(
+            "Text with given keyword ({}) doesn't exist",
+            arguments.keyword
+        ),
+    }
+    Ok(())
+}
+
+pub fn text_set(arguments: TextSetArgs) -> Result<()> {
     let source = Source::from_str(&arguments.source)?;
     let mut png = Png::try_from(&source)?;
 
-    png.remove_first_chunk(&arguments.chunk_type)
-        .context("Couldn't remove chunk")?;
+    let international = arguments.international
+        || !arguments.language.is_empty()
+        || !arguments.translated_keyword.is_empty()
+        || !text::is_latin1(&arguments.text);
+    let text = match (international, arguments.compress) {
+        (true, compressed) => TextChunk::International {
+            keyword: arguments.keyword,
+            compressed,
+            language_tag: arguments.language,
+            translated_keyword: arguments.translated_keyword,
+            text: arguments.text,
+        },
+        (false, true) => TextChunk::Compressed {
+            keyword: arguments.keyword,
+            text: arguments.text,
+        },
+        (false, false) => TextChunk::Text {
+            keyword: arguments.keyword,
+            text: arguments.text,
+        },
+    };
+    png.set_text(text).context("Couldn't set text")?;
 
     let output_path = arguments
         .output_file
         .unwrap_or(source.get_output_file_path()?);
-    fs::write(output_path, png.as_bytes()).context("Couldn't write to png file")?;
+    fs::write(output_path, png.as_bytes())
+        .map_err(PngError::Io)
+        .context("Couldn't write to png file")?;
 
     Ok(())
 }
 
-pub fn print(arguments: PrintArgs) -> Result<()> {
+pub fn text_remove(arguments: TextRemoveArgs) -> Result<()> {
     let source = Source::from_str(&arguments.source)?;
-    let png = Png::try_from(&source)?;
-    println!("Special chunk types inside file (private + ancillary):");
-    println!("{}", png);
+    let mut png = Png::try_from(&source)?;
+
+    png.remove_text(&arguments.keyword)
+        .context("Couldn't remove text")?;
+
+    let output_path = arguments
+        .output_file
+        .unwrap_or(source.get_output_file_path()?);
+    fs::write(output_path, png.as_bytes())
+        .map_err(PngError::Io)
+        .context("Couldn't write to png file")?;
+
+    Ok(())
+}
+
+pub fn create(arguments: CreateArgs) -> Result<()> {
+    let color = if arguments.noise {
+        None
+    } else {
+        Some(parse_color(&arguments.color)?)
+    };
+    let color_type = match color.as_ref().map(Vec::len) {
+        Some(4) => ihdr::TRUECOLOR_ALPHA,
+        _ => ihdr::TRUECOLOR,
+    };
+    // Size is checked before any pixel is allocated
+    let ihdr = Ihdr {
+        width: arguments.width,
+        height: arguments.height,
+        bit_depth: 8,
+        color_type,
+        compression_method: 0,
+        filter_method: 0,
+        interlace_method: 0,
+    };
+    ihdr.validate()?;
+    let samples_len = (arguments.width as usize)
+        .checked_mul(arguments.height as usize)
+        .and_then(|pixels| pixels.checked_mul(ihdr.channels().ok()?))
+        .context("Image is too large")?;
+    let mut samples: Vec<u8> = Vec::new();
+    samples
+        .try_reserve_exact(samples_len)
+        .context("Image is too large")?;
+    match color {
+        Some(color) => {
+            while samples.len() < samples_len {
+                samples.extend(&color);
+            }
+        }
+        None => {
+            samples.resize(samples_len, 0);
+            OsRng.fill_bytes(&mut samples);
+        }
+    }
+    let image = Image {
+        width: arguments.width,
+        height: arguments.height,
+        bit_depth: 8,
+        color_type,
+        samples: SampleBuffer::U8(samples),
+        palette: None,
+    };
+
+    let png = encoder::encode(&image, arguments.idat_size)?;
+    fs::write(arguments.output_file, png.as_bytes())
+        .map_err(PngError::Io)
+        .context("Couldn't write to png file.")?;
+
+    Ok(())
+}
+
+/// Parses colour written as hex RRGGBB or RRGGBBAA
+fn parse_color(color: &str) -> Result<Vec<u8>> {
+    let color = color.strip_prefix('#').unwrap_or(color);
+    if !matches!(color.len(), 6 | 8) || !color.bytes().all(|byte| byte.is_ascii_hexdigit()) {
+        bail!("Colour should be written as hex RRGGBB or RRGGBBAA");
+    }
+    (0..color.len())
+        .step_by(2)
+        .map(|index| u8::from_str_radix(&color[index..index + 2], 16).context("Invalid colour"))
+        .collect()
+}
+
+pub fn validate(arguments: ValidateArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let (png, diagnostics) = if arguments.lenient {
+        let (png, mut diagnostics) = Png::parse_lenient(&source.read_bytes()?)?;
+        // Trailing data is reported by validation as a warning
+        diagnostics.retain(|diagnostic| !matches!(diagnostic, Diagnostic::TrailingData { .. }));
+        (png, diagnostics)
+    } else {
+        // Structure (including IHDR) is checked by validation, only unreadable chunks are fatal
+        let png = Png::read_chunks(source.open()?).context("Couldn't parse png file.")?;
+        (png, Vec::new())
+    };
+
+    let violations = validation::validate(&png);
+    let errors = diagnostics.len()
+        + violations
+            .iter()
+            .filter(|violation| violation.severity == Severity::Error)
+            .count();
+    let warnings = violations.len() + diagnostics.len() - errors;
+    if arguments.format == OutputFormat::Text {
+        for diagnostic in &diagnostics {
+            println!("error: {}", diagnostic);
+        }
+        for violation in &violations {
+            println!("{}", violation);
+        }
+    } else {
+        let problems: Vec<ProblemRecord> = diagnostics
+            .iter()
+            .map(ProblemRecord::from_diagnostic)
+            .chain(violations.iter().map(ProblemRecord::from_violation))
+            .collect();
+        print_problems(problems, errors, warnings, arguments.format)?;
+    }
+    if errors > 0 {
+        bail!("{} error(s) found", errors);
+    }
+    if arguments.format == OutputFormat::Text {
+        println!("File is valid ({} warning(s))", warnings);
+    }
+
+    Ok(())
+}
+
+/// Problem found by validation, as printed in machine-readable formats
+#[derive(Serialize)]
+struct ProblemRecord {
+    severity: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    index: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    offset: Option<usize>,
+    message: String,
+}
+
+impl ProblemRecord {
+    fn from_diagnostic(diagnostic: &Diagnostic) -> ProblemRecord {
+        let (index, offset) = match diagnostic {
+            Diagnostic::CrcMismatch { index, offset, .. } => (Some(*index), *offset),
+            Diagnostic::TruncatedChunk { offset, .. }
+            | Diagnostic::InvalidChunk { offset, .. }
+            | Diagnostic::TrailingData { offset, .. } => (None, *offset),
+        };
+        ProblemRecord {
+            severity: "error",
+            index,
+            offset: Some(offset),
+            message: diagnostic.to_string(),
+        }
+    }
+
+    fn from_violation(violation: &Violation) -> ProblemRecord {
+        ProblemRecord {
+            severity: match violation.severity {
+                Severity::Error => "error",
+                Severity::Warning => "warning",
+            },
+            index: violation.location.map(|(index, _)| index),
+            offset: violation.location.map(|(_, offset)| offset),
+            message: violation.message.clone(),
+        }
+    }
+}
+
+/// Result of validation, as printed in machine-readable formats
+#[derive(Serialize)]
+struct ValidationReport {
+    valid: bool,
+    errors: usize,
+    warnings: usize,
+    problems: Vec<ProblemRecord>,
+}
+
+fn print_problems(
+    problems: Vec<ProblemRecord>,
+    errors: usize,
+    warnings: usize,
+    format: OutputFormat,
+) -> Result<()> {
+    if format != OutputFormat::Table {
+        return output::print_document(
+            &ValidationReport {
+                valid: errors == 0,
+                errors,
+                warnings,
+                problems,
+            },
+            format,
+        );
+    }
+    let location = |value: Option<usize>| value.map(|value| value.to_string()).unwrap_or_default();
+    let rows: Vec<Vec<String>> = problems
+        .iter()
+        .map(|problem| {
+            vec![
+                problem.severity.to_string(),
+                location(problem.index),
+                location(problem.offset),
+                problem.message.clone(),
+            ]
+        })
+        .collect();
+    output::print_table(&["SEVERITY", "INDEX", "OFFSET", "MESSAGE"], &rows);
+    Ok(())
+}
+
+pub fn repair(arguments: RepairArgs) -> Result<()> {
+    let source = Source::from_str(&arguments.source)?;
+    let (mut png, diagnostics) = Png::parse_lenient(&source.read_bytes()?)?;
+    // Data after IEND is kept unless asked otherwise, unreadable data in place of chunks is always dropped
+    let keep_trailing_data = !arguments.strip_trailing_data
+        && diagnostics
+            .iter()
+            .any(|diagnostic| matches!(diagnostic, Diagnostic::TrailingData { .. }));
+    if !keep_trailing_data {
+        png.strip_trailing_data();
+    }
+
+    let mut changes: Vec<String> = diagnostics
+        .iter()
+        .filter_map(|diagnostic| {
+            let action = match diagnostic {
+                Diagnostic::CrcMismatch { .. } => "recomputed CRC",
+                Diagnostic::TrailingData { .. } if keep_trailing_data => return None,
+                _ => "dropped",
+            };
+            Some(format!("{} - {}", diagnostic, action))
+        })
+        .collect();
+    let ends_with_iend = png
+        .chunks()
+        .last()
+        .is_some_and(|chunk| chunk.chunk_type().to_string() == "IEND");
+    if !ends_with_iend {
+        png.append_chunk(Chunk::new(ChunkType::from_str("IEND")?, Vec::new()));
+        changes.push(String::from("missing IEND chunk - added"));
+    }
+
+    if changes.is_empty() {
+        println!("Nothing to repair");
+        return Ok(());
+    }
+    for change in &changes {
+        println!("{}", change);
+    }
+    let output_path = arguments
+        .output_file
+        .unwrap_or(source.get_output_file_path()?);
+    fs::write(output_path, png.as_bytes())
+        .map_err(PngError::Io)
+        .context("Couldn't write to png file.")?;
+    println!("{} problem(s) fixed", changes.len());
+
     Ok(())
 }
@@ -0,0 +1,464 @@
+//! Encrypted envelopes of chunk data: key derived from passphrase (Argon2id)
+//! or wrapped for X25519 recipients, message sealed with XChaCha20-Poly1305.
+
+use anyhow::{bail, Error, Result};
+use argon2::{Algorithm, Argon2, Params, Version};
+use chacha20poly1305::{
+    aead::{rand_core::RngCore, Aead, AeadCore, KeyInit, OsRng, Payload},
+    XChaCha20Poly1305, XNonce,
+};
+use hkdf::Hkdf;
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+// Layout of encrypted chunk data:
+// magic (4) | version (1) | method (1) | method specific header | nonce (24) | ciphertext + tag
+//
+// Passphrase method header:
+// salt (16) | argon2id memory cost in KiB (4) | iterations (4) | parallelism (4)
+// (version 1 envelopes have only salt and always use the default parameters below)
+//
+// Recipients method header:
+// recipient count (1) | stanzas (count * (ephemeral public key (32) | wrapped file key (48)))
+//
+// First byte of magic followed by ascii letter is never valid utf-8,
+// so plain text messages can't be mistaken for encrypted ones.
+const MAGIC: [u8; 4] = [0xED, b'M', b'I', b'P'];
+const VERSION: u8 = 2;
+const LEGACY_VERSION: u8 = 1;
+const PASSPHRASE_METHOD: u8 = 1;
+const RECIPIENTS_METHOD: u8 = 2;
+
+const SALT_LEN: usize = 16;
+const KDF_PARAMS_LEN: usize = 12;
+const NONCE_LEN: usize = 24;
+const KEY_LEN: usize = 32;
+const TAG_LEN: usize = 16;
+const HEADER_LEN: usize = MAGIC.len() + 2;
+const STANZA_LEN: usize = KEY_LEN + KEY_LEN + TAG_LEN;
+
+const WRAP_KEY_INFO: &[u8] = b"edmipng-x25519";
+
+// Argon2id parameters of new envelopes (19 MiB, 2 iterations, 1 lane - OWASP recommendation)
+const ARGON2_M_COST: u32 = 19 * 1024;
+const ARGON2_T_COST: u32 = 2;
+const ARGON2_P_COST: u32 = 1;
+// Parameters are read from untrusted data, so they're limited to keep decryption cheap enough
+const ARGON2_MAX_M_COST: u32 = 1024 * 1024;
+const ARGON2_MAX_T_COST: u32 = 16;
+const ARGON2_MAX_P_COST: u32 = 16;
+
+/// Argon2id cost parameters stored in passphrase envelope
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct KdfParams {
+    /// Memory cost in KiB
+    m_cost: u32,
+    t_cost: u32,
+    p_cost: u32,
+}
+
+impl KdfParams {
+    const DEFAULT: KdfParams = KdfParams {
+        m_cost: ARGON2_M_COST,
+        t_cost: ARGON2_T_COST,
+        p_cost: ARGON2_P_COST,
+    };
+
+    fn to_bytes(self) -> Vec<u8> {
+        [self.m_cost, self.t_cost, self.p_cost]
+            .iter()
+            .flat_map(|value| value.to_be_bytes())
+            .collect()
+    }
+
+    fn from_bytes(bytes: &[u8]) -> Result<KdfParams> {
+        let value =
+            |index: usize| u32::from_be_bytes(bytes[index * 4..index * 4 + 4].try_into().unwrap());
+        let params = KdfParams {
+            m_cost: value(0),
+            t_cost: value(1),
+            p_cost: value(2),
+        };
+        if params.m_cost > ARGON2_MAX_M_COST
+            || params.t_cost > ARGON2_MAX_T_COST
+            || params.p_cost > ARGON2_MAX_P_COST
+        {
+            bail!(
+                "Key derivation parameters are too expensive: {} KiB, {} iterations, {} lanes",
+                params.m_cost,
+                params.t_cost,
+                params.p_cost
+            );
+        }
+        Ok(params)
+    }
+}
+
+/// Method used to derive key of encrypted message
+#[derive(Debug, PartialEq, Eq)]
+pub enum EncryptionMethod {
+    Passphrase,
+    Recipients,
+}
+
+/// Checks if data starts with encrypted envelope header
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Reads encryption method from envelope header
+pub fn encryption_method(envelope: &[u8]) -> Result<EncryptionMethod> {
+    if !is_encrypted(envelope) {
+        bail!("Data is not encrypted");
+    }
+    if envelope.len() < HEADER_LEN {
+        bail!("Encrypted data is too short");
+    }
+    let version = envelope[MAGIC.len()];
+    if version != VERSION && version != LEGACY_VERSION {
+        bail!("Unsupported encryption version: {}", version);
+    }
+    match envelope[MAGIC.len() + 1] {
+        PASSPHRASE_METHOD => Ok(EncryptionMethod::Passphrase),
+        RECIPIENTS_METHOD => Ok(EncryptionMethod::Recipients),
+        method => bail!("Unsupported encryption method: {}", method),
+    }
+}
+
+/// Encrypts message with key derived from passphrase (argon2id + XChaCha20-Poly1305).
+/// `associated_data` is authenticated but not stored inside the envelope.
+pub fn encrypt_with_passphrase(
+    message: &[u8],
+    passphrase: &str,
+    associated_data: &[u8],
+) -> Result<Vec<u8>> {
+    let mut salt = [0u8; SALT_LEN];
+    OsRng.fill_bytes(&mut salt);
+
+    let mut envelope = envelope_header(PASSPHRASE_METHOD);
+    envelope.extend(salt);
+    envelope.extend(KdfParams::DEFAULT.to_bytes());
+
+    let key = derive_key(passphrase, &salt, KdfParams::DEFAULT)?;
+    seal(envelope, &key, message, associated_data)
+}
+
+/// Decrypts envelope created by `encrypt_with_passphrase`
+pub fn decrypt_with_passphrase(
+    envelope: &[u8],
+    passphrase: &str,
+    associated_data: &[u8],
+) -> Result<Vec<u8>> {
+    if encryption_method(envelope)? != EncryptionMethod::Passphrase {
+        bail!("Message is not encrypted with passphrase");
+    }
+    let params_len = match envelope[MAGIC.len()] {
+        LEGACY_VERSION => 0,
+        _ => KDF_PARAMS_LEN,
+    };
+    let header_len = HEADER_LEN + SALT_LEN + params_len;
+    if envelope.len() < header_len {
+        bail!("Encrypted data is too short");
+    }
+    let salt = &envelope[HEADER_LEN..HEADER_LEN + SALT_LEN];
+    let params = match params_len {
+        0 => KdfParams::DEFAULT,
+        _ => KdfParams::from_bytes(&envelope[HEADER_LEN + SALT_LEN..header_len])?,
+    };
+
+    let key = derive_key(passphrase, salt, params)?;
+    open(envelope, header_len, &key, associated_data)
+        .map_err(|_| Error::msg("Couldn't decrypt message (wrong passphrase or corrupted data)"))
+}
+
+/// Encrypts message with random file key, which is then wrapped separately for every recipient
+/// using X25519 key agreement with fresh ephemeral key.
+pub fn encrypt_for_recipients(
+    message: &[u8],
+    recipients: &[PublicKey],
+    associated_data: &[u8],
+) -> Result<Vec<u8>> {
+    if recipients.is_empty() {
+        bail!("At least one recipient is required");
+    }
+    let count = u8::try_from(recipients.len())
+        .map_err(|_| Error::msg(format!("At most {} recipients are allowed", u8::MAX)))?;
+
+    let mut file_key = [0u8; KEY_LEN];
+    OsRng.fill_bytes(&mut file_key);
+
+    let mut envelope = envelope_header(RECIPIENTS_METHOD);
+    envelope.push(count);
+    for recipient in recipients {
+        let ephemeral_secret = EphemeralSecret::random_from_rng(OsRng);
+        let ephemeral_public = PublicKey::from(&ephemeral_secret);
+        let shared_secret = ephemeral_secret.diffie_hellman(recipient);
+        if !shared_secret.was_contributory() {
+            bail!("Invalid recipient public key");
+        }
+        let wrap_key = derive_wrap_key(shared_secret.as_bytes(), &ephemeral_public, recipient)?;
+        let wrapped_key = XChaCha20Poly1305::new(&wrap_key.into())
+            // Wrap key is unique for every stanza, so fixed nonce is safe here
+            .encrypt(&XNonce::default(), file_key.as_slice())
+            .map_err(|_| Error::msg("Couldn't wrap file key"))?;
+        envelope.extend(ephemeral_public.as_bytes());
+        envelope.extend(wrapped_key);
+    }
+
+    seal(envelope, &file_key, message, associated_data)
+}
+
+/// Decrypts envelope created by `encrypt_for_recipients`, trying identity against every recipient stanza
+pub fn decrypt_with_identity(
+    envelope: &[u8],
+    identity: &StaticSecret,
+    associated_data: &[u8],
+) -> Result<Vec<u8>> {
+    if encryption_method(envelope)? != EncryptionMethod::Recipients {
+        bail!("Message is not encrypted for recipients");
+    }
+    let count = *envelope
+        .get(HEADER_LEN)
+        .ok_or(Error::msg("Encrypted data is too short"))? as usize;
+    let stanzas_start = HEADER_LEN + 1;
+    let stanzas_end = stanzas_start + count * STANZA_LEN;
+    if envelope.len() < stanzas_end {
+        bail!("Encrypted data is too short");
+    }
+
+    let identity_public = PublicKey::from(identity);
+    let file_key = envelope[stanzas_start..stanzas_end]
+        .chunks_exact(STANZA_LEN)
+        .find_map(|stanza| {
+            let ephemeral_bytes: [u8; KEY_LEN] = stanza[..KEY_LEN].try_into().ok()?;
+            let ephemeral_public = PublicKey::from(ephemeral_bytes);
+            let shared_secret = identity.diffie_hellman(&ephemeral_public);
+            let wrap_key = derive_wrap_key(
+                shared_secret.as_bytes(),
+                &ephemeral_public,
+                &identity_public,
+            )
+            .ok()?;
+            let file_key = XChaCha20Poly1305::new(&wrap_key.into())
+                .decrypt(&XNonce::default(), &stanza[KEY_LEN..])
+                .ok()?;
+            <[u8; KEY_LEN]>::try_from(file_key).ok()
+        })
+        .ok_or(Error::msg(
+            "Given identity is not a recipient of this message",
+        ))?;
+
+    open(envelope, stanzas_end, &file_key, associated_data)
+        .map_err(|_| Error::msg("Couldn't decrypt message (corrupted data)"))
+}
+
+fn envelope_header(method: u8) -> Vec<u8> {
+    let mut header: Vec<u8> = Vec::with_capacity(HEADER_LEN);
+    header.extend(MAGIC);
+    header.push(VERSION);
+    header.push(method);
+    header
+}
+
+/// Appends nonce and ciphertext to envelope header, authenticating the whole header
+fn seal(
+    mut envelope: Vec<u8>,
+    key: &[u8; KEY_LEN],
+    message: &[u8],
+    associated_data: &[u8],
+) -> Result<Vec<u8>> {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    envelope.extend(nonce);
+
+    let aad: Vec<u8> = envelope.iter().chain(associated_data).copied().collect();
+    let ciphertext = XChaCha20Poly1305::new(key.into())
+        .encrypt(
+            &nonce,
+            Payload {
+                msg: message,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| Error::msg("Couldn't encrypt message"))?;
+    envelope.extend(ciphertext);
+
+    Ok(envelope)
+}
+
+/// Opens ciphertext placed after header of length `header_len` (not including nonce)
+fn open(
+    envelope: &[u8],
+    header_len: usize,
+    key: &[u8; KEY_LEN],
+    associated_data: &[u8],
+) -> Result<Vec<u8>> {
+    if envelope.len() < header_len + NONCE_LEN {
+        bail!("Encrypted data is too short");
+    }
+    let (header, ciphertext) = envelope.split_at(header_len + NONCE_LEN);
+    let nonce = XNonce::from_slice(&header[header_len..]);
+
+    let aad: Vec<u8> = header.iter().chain(associated_data).copied().collect();
+    XChaCha20Poly1305::new(key.into())
+        .decrypt(
+            nonce,
+            Payload {
+                msg: ciphertext,
+                aad: &aad,
+            },
+        )
+        .map_err(|_| Error::msg("Couldn't decrypt message"))
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], params: KdfParams) -> Result<[u8; KEY_LEN]> {
+    let error =
+        |err: argon2::Error| Error::msg(format!("Couldn't derive key from passphrase: {}", err));
+    let params =
+        Params::new(params.m_cost, params.t_cost, params.p_cost, Some(KEY_LEN)).map_err(error)?;
+    let mut key = [0u8; KEY_LEN];
+    Argon2::new(Algorithm::Argon2id, Version::V0x13, params)
+        .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+        .map_err(error)?;
+    Ok(key)
+}
+
+fn derive_wrap_key(
+    shared_secret: &[u8],
+    ephemeral_public: &PublicKey,
+    recipient: &PublicKey,
+) -> Result<[u8; KEY_LEN]> {
+    let salt: Vec<u8> = ephemeral_public
+        .as_bytes()
+        .iter()
+        .chain(recipient.as_bytes())
+        .copied()
+        .collect();
+    let mut key = [0u8; KEY_LEN];
+    Hkdf::<Sha256>::new(Some(&salt), shared_secret)
+        .expand(WRAP_KEY_INFO, &mut key)
+        .map_err(|_| Error::msg("Couldn't derive wrap key"))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"This is where your secret message will be!";
+    const AAD: &[u8] = b"ruSt";
+
+    fn identity() -> StaticSecret {
+        StaticSecret::random_from_rng(OsRng)
+    }
+
+    #[test]
+    fn test_encrypt_decrypt() {
+        let envelope = encrypt_with_passphrase(MESSAGE, "passphrase", AAD).unwrap();
+        assert!(is_encrypted(&envelope));
+        assert_eq!(
+            encryption_method(&envelope).unwrap(),
+            EncryptionMethod::Passphrase
+        );
+        let message = decrypt_with_passphrase(&envelope, "passphrase", AAD).unwrap();
+        assert_eq!(message, MESSAGE);
+    }
+
+    #[test]
+    fn test_decrypt_wrong_passphrase() {
+        let envelope = encrypt_with_passphrase(MESSAGE, "passphrase", AAD).unwrap();
+        assert!(decrypt_with_passphrase(&envelope, "wrong", AAD).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_tampered() {
+        let mut envelope = encrypt_with_passphrase(MESSAGE, "passphrase", AAD).unwrap();
+        let last = envelope.len() - 1;
+        envelope[last] ^= 1;
+        assert!(decrypt_with_passphrase(&envelope, "passphrase", AAD).is_err());
+
+        let envelope = encrypt_with_passphrase(MESSAGE, "passphrase", AAD).unwrap();
+        assert!(decrypt_with_passphrase(&envelope, "passphrase", b"otHr").is_err());
+    }
+
+    #[test]
+    fn test_derive_key_known_answer() {
+        let key = derive_key("passphrase", b"edmipng salt 16b", KdfParams::DEFAULT).unwrap();
+        assert_eq!(
+            key,
+            [
+                0x51, 0x8b, 0x48, 0x28, 0x44, 0x4d, 0xe0, 0xda, 0xb2, 0xb4, 0x0d, 0x5a, 0xc4, 0x44,
+                0xe1, 0xcc, 0x3c, 0xd1, 0xe5, 0x4c, 0x40, 0x18, 0xf5, 0x60, 0x7e, 0x8d, 0xc7, 0x17,
+                0x18, 0x92, 0x54, 0x44
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decrypt_legacy_envelope() {
+        // Version 1 envelopes don't store key derivation parameters
+        let salt = [7u8; SALT_LEN];
+        let mut envelope = MAGIC.to_vec();
+        envelope.extend([LEGACY_VERSION, PASSPHRASE_METHOD]);
+        envelope.extend(salt);
+        let key = derive_key("passphrase", &salt, KdfParams::DEFAULT).unwrap();
+        let envelope = seal(envelope, &key, MESSAGE, AAD).unwrap();
+        let message = decrypt_with_passphrase(&envelope, "passphrase", AAD).unwrap();
+        assert_eq!(message, MESSAGE);
+    }
+
+    #[test]
+    fn test_decrypt_expensive_params() {
+        let mut envelope = encrypt_with_passphrase(MESSAGE, "passphrase", AAD).unwrap();
+        let m_cost = HEADER_LEN + SALT_LEN;
+        envelope[m_cost..m_cost + 4].copy_from_slice(&(ARGON2_MAX_M_COST + 1).to_be_bytes());
+        let error = decrypt_with_passphrase(&envelope, "passphrase", AAD).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("Key derivation parameters are too expensive"));
+    }
+
+    #[test]
+    fn test_plain_message_is_not_encrypted() {
+        assert!(!is_encrypted(MESSAGE));
+        assert!(decrypt_with_passphrase(MESSAGE, "passphrase", AAD).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_decrypt_for_recipients() {
+        let identities = [identity(), identity(), identity()];
+        let recipients: Vec<PublicKey> = identities.iter().map(PublicKey::from).collect();
+        let envelope = encrypt_for_recipients(MESSAGE, &recipients, AAD).unwrap();
+        assert_eq!(
+            encryption_method(&envelope).unwrap(),
+            EncryptionMethod::Recipients
+        );
+        for identity in &identities {
+            let message = decrypt_with_identity(&envelope, identity, AAD).unwrap();
+            assert_eq!(message, MESSAGE);
+        }
+    }
+
+    #[test]
+    fn test_decrypt_with_wrong_identity() {
+        let recipients = [PublicKey::from(&identity())];
+        let envelope = encrypt_for_recipients(MESSAGE, &recipients, AAD).unwrap();
+        assert!(decrypt_with_identity(&envelope, &identity(), AAD).is_err());
+    }
+
+    #[test]
+    fn test_decrypt_for_recipients_tampered() {
+        let identity = identity();
+        let recipients = [PublicKey::from(&identity)];
+        let envelope = encrypt_for_recipients(MESSAGE, &recipients, AAD).unwrap();
+
+        // Last byte of wrapped file key
+        let mut tampered = envelope.clone();
+        tampered[HEADER_LEN + STANZA_LEN] ^= 1;
+        assert!(decrypt_with_identity(&tampered, &identity, AAD).is_err());
+
+        let mut tampered = envelope.clone();
+        let last = tampered.len() - 1;
+        tampered[last] ^= 1;
+        assert!(decrypt_with_identity(&tampered, &identity, AAD).is_err());
+    }
+}
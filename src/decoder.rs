@@ -0,0 +1,515 @@
+//! Decoding image data (IDAT chunks) into samples.
+
+use std::io::Read;
+
+use anyhow::{bail, Context, Error, Result};
+use flate2::read::ZlibDecoder;
+
+use crate::{
+    filter,
+    ihdr::{self, Ihdr},
+    png::Png,
+};
+
+pub const PLTE_CHUNK_TYPE: &str = "PLTE";
+
+// http://www.libpng.org/pub/png/spec/1.2/PNG-DataRep.html#DR.Interlaced-data-order
+// (x offset, y offset, x step, y step) of every Adam7 pass
+const ADAM7_PASSES: [(usize, usize, usize, usize); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+const NO_INTERLACE_PASSES: [(usize, usize, usize, usize); 1] = [(0, 0, 1, 1)];
+
+/// Samples of decoded image, stored in the smallest type able to hold them
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum SampleBuffer {
+    /// Samples of images with bit depth up to 8 (values aren't rescaled, e.g. 1-bit samples are 0 or 1)
+    U8(Vec<u8>),
+    /// Samples of 16-bit images
+    U16(Vec<u16>),
+}
+
+/// Decoded image
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    /// Samples of all pixels, row by row, left to right, one sample per channel
+    pub samples: SampleBuffer,
+    /// Palette entries (only for indexed-colour images)
+    pub palette: Option<Vec<[u8; 3]>>,
+}
+
+/// Decodes pixels of the image stored in IDAT chunks
+pub fn decode(png: &Png) -> Result<Image> {
+    let ihdr = png.ihdr()?;
+    let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+    let channels = ihdr.channels()?;
+    let passes = passes(&ihdr)?;
+
+    // Sizes come from IHDR, so they can overflow on 32-bit targets or for 16-bit images
+    let too_large = || Error::msg(format!("Image is too large: {}x{}", width, height));
+    let mut expected_len: usize = 0;
+    for &(x, y, x_step, y_step) in passes {
+        let (pass_width, pass_height) = pass_size(width, height, x, y, x_step, y_step);
+        if pass_width > 0 {
+            expected_len = (ihdr.row_len(pass_width as u32)? + 1)
+                .checked_mul(pass_height)
+                .and_then(|pass_len| expected_len.checked_add(pass_len))
+                .ok_or_else(too_large)?;
+        }
+    }
+    let data = inflate_image_data(png, expected_len)?;
+    if data.len() < expected_len {
+        bail!(
+            "Image data is too short: expected {} bytes, got {}",
+            expected_len,
+            data.len()
+        );
+    }
+
+    let samples_len = width
+        .checked_mul(height)
+        .and_then(|pixels| pixels.checked_mul(channels))
+        .ok_or_else(too_large)?;
+    // Unpacking straight into the final sample type, so that images with low bit depth
+    // don't need a temporary buffer of 16-bit samples
+    let samples = match ihdr.bit_depth {
+        16 => SampleBuffer::U16(deinterlace(
+            &ihdr,
+            passes,
+            &data,
+            samples_len,
+            unpack_row_16,
+        )?),
+        bit_depth => SampleBuffer::U8(deinterlace(&ihdr, passes, &data, samples_len, |row| {
+            unpack_row(row, bit_depth)
+        })?),
+    };
+
+    let palette = if ihdr.color_type == ihdr::INDEXED {
+        Some(read_palette(png)?)
+    } else {
+        None
+    };
+    Ok(Image {
+        width: ihdr.width,
+        height: ihdr.height,
+        bit_depth: ihdr.bit_depth,
+        color_type: ihdr.color_type,
+        samples,
+        palette,
+    })
+}
+
+/// Inflates concatenated IDAT data, refusing to produce more than `expected_len` bytes
+/// (one extra byte is kept, so that data longer than expected can be detected)
+pub fn inflate_image_data(png: &Png, expected_len: usize) -> Result<Vec<u8>> {
+    let mut data = Vec::new();
+    ZlibDecoder::new(png.image_data().as_slice())
+        .take(expected_len as u64 + 1)
+        .read_to_end(&mut data)
+        .context("Couldn't decompress image data")?;
+    Ok(data)
+}
+
+/// Unfilters rows of every pass and puts their pixels at their places in the image,
+/// `unpack` splits unfiltered row into samples
+fn deinterlace<T, F>(
+    ihdr: &Ihdr,
+    passes: &[(usize, usize, usize, usize)],
+    data: &[u8],
+    samples_len: usize,
+    unpack: F,
+) -> Result<Vec<T>>
+where
+    T: Copy + Default,
+    F: Fn(&[u8]) -> Vec<T>,
+{
+    let (width, height) = (ihdr.width as usize, ihdr.height as usize);
+    let channels = ihdr.channels()?;
+    let bytes_per_pixel = ihdr.bytes_per_pixel()?;
+    let mut samples = Vec::new();
+    samples
+        .try_reserve_exact(samples_len)
+        .with_context(|| format!("Image is too large: {}x{}", width, height))?;
+    samples.resize(samples_len, T::default());
+
+    let mut offset = 0;
+    for &(x, y, x_step, y_step) in passes {
+        let (pass_width, pass_height) = pass_size(width, height, x, y, x_step, y_step);
+        if pass_width == 0 || pass_height == 0 {
+            continue;
+        }
+        // Length of all passes was already checked against image data
+        let row_len = ihdr.row_len(pass_width as u32)?;
+        let pass_len = pass_height * (row_len + 1);
+        let (rows, _) = filter::unfilter(
+            &data[offset..offset + pass_len],
+            pass_height,
+            row_len,
+            bytes_per_pixel,
+        )?;
+        offset += pass_len;
+
+        for (pass_y, row) in rows.chunks(row_len).enumerate() {
+            let row_samples = unpack(row);
+            let image_y = y + pass_y * y_step;
+            for (pass_x, pixel) in row_samples.chunks(channels).take(pass_width).enumerate() {
+                let start = ((image_y * width) + x + pass_x * x_step) * channels;
+                samples[start..start + channels].copy_from_slice(pixel);
+            }
+        }
+    }
+    Ok(samples)
+}
+
+fn passes(ihdr: &Ihdr) -> Result<&'static [(usize, usize, usize, usize)]> {
+    match ihdr.interlace_method {
+        0 => Ok(&NO_INTERLACE_PASSES),
+        1 => Ok(&ADAM7_PASSES),
+        method => bail!("Invalid interlace method: {}", method),
+    }
+}
+
+fn pass_size(
+    width: usize,
+    height: usize,
+    x: usize,
+    y: usize,
+    x_step: usize,
+    y_step: usize,
+) -> (usize, usize) {
+    (
+        width.saturating_sub(x).div_ceil(x_step),
+        height.saturating_sub(y).div_ceil(y_step),
+    )
+}
+
+/// Splits unfiltered row of image with bit depth up to 8 into separate samples
+/// (trailing padding bits are returned as well)
+fn unpack_row(row: &[u8], bit_depth: u8) -> Vec<u8> {
+    if bit_depth == 8 {
+        return row.to_vec();
+    }
+    let mask = (1u8 << bit_depth) - 1;
+    row.iter()
+        .flat_map(|&byte| {
+            (0..8 / bit_depth).map(move |index| (byte >> (8 - bit_depth * (index + 1))) & mask)
+        })
+        .collect()
+}
+
+/// Splits unfiltered row of 16-bit image into separate samples
+fn unpack_row_16(row: &[u8]) -> Vec<u16> {
+    row.chunks_exact(2)
+        .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]))
+        .collect()
+}
+
+fn read_palette(png: &Png) -> Result<Vec<[u8; 3]>> {
+    let data = png
+        .chunk_by_type(PLTE_CHUNK_TYPE)
+        .ok_or(Error::msg(
+            "Indexed-colour image doesn't contain PLTE chunk",
+        ))?
+        .data();
+    if data.is_empty() || data.len() % 3 != 0 || data.len() > 256 * 3 {
+        bail!("Invalid PLTE chunk length: {}", data.len());
+    }
+    Ok(data
+        .chunks_exact(3)
+        .map(|entry| [entry[0], entry[1], entry[2]])
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashSet, fs, io::Write, path::Path, str::FromStr};
+
+    use flate2::{write::ZlibEncoder, Compression};
+
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+    // Every valid combination of colour type, number of channels and bit depth
+    const FORMATS: [(u8, usize, u8); 15] = [
+        (ihdr::GREYSCALE, 1, 1),
+        (ihdr::GREYSCALE, 1, 2),
+        (ihdr::GREYSCALE, 1, 4),
+        (ihdr::GREYSCALE, 1, 8),
+        (ihdr::GREYSCALE, 1, 16),
+        (ihdr::TRUECOLOR, 3, 8),
+        (ihdr::TRUECOLOR, 3, 16),
+        (ihdr::INDEXED, 1, 1),
+        (ihdr::INDEXED, 1, 2),
+        (ihdr::INDEXED, 1, 4),
+        (ihdr::INDEXED, 1, 8),
+        (ihdr::GREYSCALE_ALPHA, 2, 8),
+        (ihdr::GREYSCALE_ALPHA, 2, 16),
+        (ihdr::TRUECOLOR_ALPHA, 4, 8),
+        (ihdr::TRUECOLOR_ALPHA, 4, 16),
+    ];
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn testing_samples(width: usize, height: usize, channels: usize, bit_depth: u8) -> Vec<u16> {
+        let max = ((1u32 << bit_depth) - 1) as usize;
+        (0..width * height * channels)
+            .map(|index| ((index * 7919 + index / 3) % (max + 1)) as u16)
+            .collect()
+    }
+
+    fn pack_row(samples: &[u16], bit_depth: u8) -> Vec<u8> {
+        match bit_depth {
+            16 => samples
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+            8 => samples.iter().map(|&sample| sample as u8).collect(),
+            _ => samples
+                .chunks(8 / bit_depth as usize)
+                .map(|group| {
+                    group
+                        .iter()
+                        .enumerate()
+                        .fold(0u8, |byte, (index, &sample)| {
+                            byte | (sample as u8) << (8 - bit_depth as usize * (index + 1))
+                        })
+                })
+                .collect(),
+        }
+    }
+
+    /// Builds png from samples, independently of decoder, cycling through all filter types
+    fn encode_image(
+        (width, height): (usize, usize),
+        (color_type, channels, bit_depth): (u8, usize, u8),
+        interlace: u8,
+        samples: &[u16],
+    ) -> Png {
+        let bpp = (channels * bit_depth as usize).div_ceil(8);
+        let passes: &[(usize, usize, usize, usize)] = match interlace {
+            0 => &NO_INTERLACE_PASSES,
+            _ => &ADAM7_PASSES,
+        };
+        let mut data = Vec::new();
+        let mut filter_type = 0;
+        for &(x0, y0, x_step, y_step) in passes {
+            let mut previous: Option<Vec<u8>> = None;
+            for y in (y0..height).step_by(y_step) {
+                let pixels: Vec<u16> = (x0..width)
+                    .step_by(x_step)
+                    .flat_map(|x| {
+                        samples[(y * width + x) * channels..(y * width + x + 1) * channels].to_vec()
+                    })
+                    .collect();
+                if pixels.is_empty() {
+                    continue;
+                }
+                let row = pack_row(&pixels, bit_depth);
+                data.push(filter_type);
+                data.extend(filter::filter_row(
+                    &row,
+                    previous.as_deref(),
+                    bpp,
+                    filter_type,
+                ));
+                filter_type = (filter_type + 1) % 5;
+                previous = Some(row);
+            }
+        }
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+
+        let mut ihdr = Vec::new();
+        ihdr.extend((width as u32).to_be_bytes());
+        ihdr.extend((height as u32).to_be_bytes());
+        ihdr.extend([bit_depth, color_type, 0, 0, interlace]);
+        let mut chunks = vec![chunk("IHDR", ihdr)];
+        if color_type == ihdr::INDEXED {
+            chunks.push(chunk(
+                "PLTE",
+                (0..=255).flat_map(|i| [i, 255 - i, i / 2]).collect(),
+            ));
+        }
+        // Split image data into a few chunks to check they are concatenated
+        let third = compressed.len().div_ceil(3);
+        chunks.extend(
+            compressed
+                .chunks(third)
+                .map(|part| chunk("IDAT", part.to_vec())),
+        );
+        chunks.push(chunk("IEND", vec![]));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_decode_all_formats() {
+        // Sizes smaller than Adam7 block and not multiple of 8 exercise empty passes and padding bits
+        for (width, height) in [(1, 1), (3, 2), (13, 11)] {
+            for format in FORMATS {
+                let samples = testing_samples(width, height, format.1, format.2);
+                for interlace in [0, 1] {
+                    let png = encode_image((width, height), format, interlace, &samples);
+                    let image = png.decode_image().unwrap();
+                    assert_eq!(image.width as usize, width);
+                    assert_eq!(image.height as usize, height);
+                    assert_eq!((image.color_type, image.bit_depth), (format.0, format.2));
+                    let decoded: Vec<u16> = match image.samples {
+                        SampleBuffer::U8(samples) => {
+                            assert!(format.2 <= 8);
+                            samples.into_iter().map(u16::from).collect()
+                        }
+                        SampleBuffer::U16(samples) => {
+                            assert_eq!(format.2, 16);
+                            samples
+                        }
+                    };
+                    assert_eq!(decoded, samples, "{:?} interlace {}", format, interlace);
+                    assert_eq!(image.palette.is_some(), format.0 == ihdr::INDEXED);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_unpack_row() {
+        assert_eq!(unpack_row(&[0b1011_0001], 1), [1, 0, 1, 1, 0, 0, 0, 1]);
+        assert_eq!(unpack_row(&[0b1011_0001], 2), [2, 3, 0, 1]);
+        assert_eq!(unpack_row(&[0b1011_0001], 4), [11, 1]);
+        assert_eq!(unpack_row(&[0b1011_0001], 8), [0b1011_0001]);
+        assert_eq!(unpack_row_16(&[1, 2]), [258]);
+    }
+
+    #[test]
+    fn test_decode_truncated_data() {
+        let samples = testing_samples(8, 8, 3, 8);
+        let mut png = encode_image((8, 8), (ihdr::TRUECOLOR, 3, 8), 0, &samples);
+        let data = png.image_data();
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        let mut inflated = Vec::new();
+        ZlibDecoder::new(data.as_slice())
+            .read_to_end(&mut inflated)
+            .unwrap();
+        encoder.write_all(&inflated[..inflated.len() - 1]).unwrap();
+        png.set_image_data(&encoder.finish().unwrap(), 1024)
+            .unwrap();
+        assert!(png.decode_image().is_err());
+    }
+
+    #[test]
+    fn test_decode_indexed_without_palette() {
+        let samples = testing_samples(4, 4, 1, 8);
+        let png = encode_image((4, 4), (ihdr::INDEXED, 1, 8), 0, &samples);
+        let chunks = png
+            .chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().to_string() != PLTE_CHUNK_TYPE)
+            .map(|chunk| Chunk::new(chunk.chunk_type().clone(), chunk.data().to_vec()))
+            .collect();
+        assert!(Png::from_chunks(chunks).decode_image().is_err());
+    }
+
+    #[test]
+    fn test_decode_huge_dimensions() {
+        let mut ihdr = Vec::new();
+        ihdr.extend(2147483647u32.to_be_bytes());
+        ihdr.extend(2147483647u32.to_be_bytes());
+        ihdr.extend([16, ihdr::TRUECOLOR_ALPHA, 0, 0, 0]);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&[0; 64]).unwrap();
+        let png = Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("IDAT", encoder.finish().unwrap()),
+            chunk("IEND", vec![]),
+        ]);
+        assert!(png.decode_image().is_err());
+    }
+
+    /// Decodes image with the png crate, the reference for PngSuite test
+    fn reference_samples(path: &Path) -> SampleBuffer {
+        let mut decoder = ::png::Decoder::new(fs::File::open(path).unwrap());
+        decoder.set_transformations(::png::Transformations::IDENTITY);
+        let mut reader = decoder.read_info().unwrap();
+        let mut buffer = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut buffer).unwrap();
+        let row_samples = frame.width as usize * frame.color_type.samples();
+        let rows = buffer[..frame.buffer_size()].chunks(frame.line_size);
+        match frame.bit_depth {
+            ::png::BitDepth::Sixteen => SampleBuffer::U16(rows.flat_map(unpack_row_16).collect()),
+            bit_depth => SampleBuffer::U8(
+                rows.flat_map(|row| {
+                    let mut samples = unpack_row(row, bit_depth as u8);
+                    samples.truncate(row_samples);
+                    samples
+                })
+                .collect(),
+            ),
+        }
+    }
+
+    /// Decodes every image of PngSuite (http://www.schaik.com/pngsuite/) stored in tests/pngsuite
+    /// and compares its samples with the ones decoded by the png crate.
+    /// Corrupted x* images must be rejected.
+    #[test]
+    fn test_pngsuite() {
+        let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/pngsuite");
+        let mut names: Vec<String> = fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().to_string_lossy().into_owned())
+            .filter(|name| name.ends_with(".png"))
+            .collect();
+        names.sort();
+
+        let (mut formats, mut checked) = (HashSet::new(), Vec::new());
+        for name in &names {
+            let path = directory.join(name);
+            let decoded = Png::from_file(&path).and_then(|png| png.decode_image());
+            if name.starts_with('x') {
+                assert!(decoded.is_err(), "{}", name);
+                continue;
+            }
+            let image = decoded.unwrap_or_else(|err| panic!("{}: {:#}", name, err));
+            assert_eq!(image.samples, reference_samples(&path), "{}", name);
+            let interlace = name.as_bytes()[3];
+            formats.insert((image.color_type, image.bit_depth, interlace));
+            checked.push(name.as_str());
+        }
+
+        // Every valid format has to be covered, interlaced and not
+        for (color_type, _, bit_depth) in FORMATS {
+            for interlace in [b'n', b'i'] {
+                assert!(
+                    formats.contains(&(color_type, bit_depth, interlace)),
+                    "No PngSuite image of colour type {}, bit depth {}, interlace {}",
+                    color_type,
+                    bit_depth,
+                    interlace as char
+                );
+            }
+        }
+        // As well as all filter types and odd sizes (Adam7 passes smaller than the image)
+        for prefix in [
+            "f00", "f01", "f02", "f03", "f04", "s01", "s09", "s32", "s40",
+        ] {
+            assert!(
+                checked.iter().any(|name| name.starts_with(prefix)),
+                "No {}* PngSuite image",
+                prefix
+            );
+        }
+        assert!(names.iter().any(|name| name.starts_with('x')));
+    }
+}
@@ -0,0 +1,274 @@
+//! Encoding samples into a new png file.
+
+use std::{io::Write, str::FromStr};
+
+use anyhow::{bail, Context, Result};
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    decoder::{Image, SampleBuffer, PLTE_CHUNK_TYPE},
+    filter,
+    ihdr::{self, Ihdr},
+    png::Png,
+};
+
+/// Default maximum size of data stored in single IDAT chunk
+pub const DEFAULT_IDAT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Builds non-interlaced png from image, choosing filter type of every row adaptively
+/// and splitting compressed data into IDAT chunks of at most `idat_chunk_size` bytes
+pub fn encode(image: &Image, idat_chunk_size: usize) -> Result<Png> {
+    let ihdr = Ihdr {
+        width: image.width,
+        height: image.height,
+        bit_depth: image.bit_depth,
+        color_type: image.color_type,
+        compression_method: 0,
+        filter_method: 0,
+        interlace_method: 0,
+    };
+    check_image(image, &ihdr)?;
+    if idat_chunk_size == 0 {
+        bail!("IDAT chunk size must be greater than 0");
+    }
+
+    let (width, height) = (image.width as usize, image.height as usize);
+    let channels = ihdr.channels()?;
+    let bytes_per_pixel = ihdr.bytes_per_pixel()?;
+    let row_len = ihdr.row_len(image.width)?;
+    let row_samples = width * channels;
+
+    let mut data: Vec<u8> = Vec::with_capacity(height * (row_len + 1));
+    let mut previous: Option<Vec<u8>> = None;
+    for y in 0..height {
+        let row = match &image.samples {
+            SampleBuffer::U8(samples) => pack_row(
+                &samples[y * row_samples..(y + 1) * row_samples],
+                image.bit_depth,
+            ),
+            SampleBuffer::U16(samples) => samples[y * row_samples..(y + 1) * row_samples]
+                .iter()
+                .flat_map(|sample| sample.to_be_bytes())
+                .collect(),
+        };
+        let (filter_type, filtered) = adaptive_filter(&row, previous.as_deref(), bytes_per_pixel);
+        data.push(filter_type);
+        data.extend(filtered);
+        previous = Some(row);
+    }
+
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+    encoder
+        .write_all(&data)
+        .context("Couldn't compress image data")?;
+    let compressed = encoder.finish().context("Couldn't compress image data")?;
+
+    let mut chunks = vec![ihdr.to_chunk()?];
+    if let Some(palette) = &image.palette {
+        chunks.push(Chunk::new(
+            ChunkType::from_str(PLTE_CHUNK_TYPE)?,
+            palette.concat(),
+        ));
+    }
+    for part in compressed.chunks(idat_chunk_size) {
+        chunks.push(Chunk::new(ChunkType::from_str("IDAT")?, part.to_vec()));
+    }
+    chunks.push(Chunk::new(ChunkType::from_str("IEND")?, Vec::new()));
+    Ok(Png::from_chunks(chunks))
+}
+
+fn check_image(image: &Image, ihdr: &Ihdr) -> Result<()> {
+    ihdr.validate()?;
+
+    let expected_len = image.width as usize * image.height as usize * ihdr.channels()?;
+    let max_sample = (1u32 << image.bit_depth) - 1;
+    let (len, in_range) = match &image.samples {
+        SampleBuffer::U8(samples) if image.bit_depth <= 8 => (
+            samples.len(),
+            samples.iter().all(|&sample| sample as u32 <= max_sample),
+        ),
+        SampleBuffer::U16(samples) if image.bit_depth == 16 => (samples.len(), true),
+        _ => bail!("16-bit images need 16-bit samples, other bit depths need 8-bit samples"),
+    };
+    if len != expected_len {
+        bail!(
+            "Image should have {} samples, but {} were given",
+            expected_len,
+            len
+        );
+    }
+    if !in_range {
+        bail!("Samples don't fit in {} bits", image.bit_depth);
+    }
+
+    match (&image.palette, image.color_type) {
+        (Some(palette), ihdr::INDEXED) => {
+            if palette.is_empty() || palette.len() > 1 << image.bit_depth {
+                bail!(
+                    "Palette should have between 1 and {} entries",
+                    1 << image.bit_depth
+                );
+            }
+            if let SampleBuffer::U8(samples) = &image.samples {
+                if samples.iter().any(|&index| index as usize >= palette.len()) {
+                    bail!("Palette index out of range");
+                }
+            }
+        }
+        (None, ihdr::INDEXED) => bail!("Indexed-colour image needs palette"),
+        (Some(_), _) => bail!("Only indexed-colour images can have palette"),
+        (None, _) => {}
+    }
+    Ok(())
+}
+
+/// Joins samples of depth up to 8 bits into bytes, padding last byte with zeros
+fn pack_row(samples: &[u8], bit_depth: u8) -> Vec<u8> {
+    if bit_depth == 8 {
+        return samples.to_vec();
+    }
+    let per_byte = (8 / bit_depth) as usize;
+    samples
+        .chunks(per_byte)
+        .map(|group| {
+            group.iter().enumerate().fold(0, |byte, (index, &sample)| {
+                byte | sample << (8 - bit_depth as usize * (index + 1))
+            })
+        })
+        .collect()
+}
+
+/// Filters row with every filter type and picks the one with the smallest sum of absolute
+/// values of filtered bytes (treated as signed), as recommended by the png specification
+fn adaptive_filter(row: &[u8], previous: Option<&[u8]>, bytes_per_pixel: usize) -> (u8, Vec<u8>) {
+    [
+        filter::NONE,
+        filter::SUB,
+        filter::UP,
+        filter::AVERAGE,
+        filter::PAETH,
+    ]
+    .into_iter()
+    .map(|filter_type| {
+        (
+            filter_type,
+            filter::filter_row(row, previous, bytes_per_pixel, filter_type),
+        )
+    })
+    .min_by_key(|(_, filtered)| {
+        filtered
+            .iter()
+            .map(|&byte| (byte as i8).unsigned_abs() as u64)
+            .sum::<u64>()
+    })
+    .expect("There is at least one filter type")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_image(color_type: u8, bit_depth: u8, channels: usize) -> Image {
+        let (width, height) = (13u32, 7u32);
+        let max = (1u32 << bit_depth) - 1;
+        let values = (0..width as usize * height as usize * channels)
+            .map(|index| ((index as u32 * 37 + index as u32 / 5) % (max + 1)) as u16);
+        let samples = match bit_depth {
+            16 => SampleBuffer::U16(values.collect()),
+            _ => SampleBuffer::U8(values.map(|value| value as u8).collect()),
+        };
+        let palette = (color_type == ihdr::INDEXED)
+            .then(|| (0..=max).map(|index| [index as u8, 0, 255]).collect());
+        Image {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            samples,
+            palette,
+        }
+    }
+
+    #[test]
+    fn test_encode_decode_round_trip() {
+        for (color_type, bit_depth, channels) in [
+            (ihdr::GREYSCALE, 1, 1),
+            (ihdr::GREYSCALE, 4, 1),
+            (ihdr::GREYSCALE, 16, 1),
+            (ihdr::TRUECOLOR, 8, 3),
+            (ihdr::TRUECOLOR, 16, 3),
+            (ihdr::INDEXED, 2, 1),
+            (ihdr::INDEXED, 8, 1),
+            (ihdr::GREYSCALE_ALPHA, 8, 2),
+            (ihdr::TRUECOLOR_ALPHA, 16, 4),
+        ] {
+            let image = testing_image(color_type, bit_depth, channels);
+            let png = encode(&image, DEFAULT_IDAT_CHUNK_SIZE).unwrap();
+            let bytes = png.as_bytes();
+            let decoded = Png::try_from(bytes.as_slice()).unwrap().decode_image();
+            assert_eq!(decoded.unwrap(), image);
+        }
+    }
+
+    #[test]
+    fn test_encode_chunk_layout() {
+        let image = testing_image(ihdr::TRUECOLOR_ALPHA, 16, 4);
+        let png = encode(&image, 100).unwrap();
+        let chunk_types: Vec<String> = png
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(chunk_types.first().unwrap(), "IHDR");
+        assert_eq!(chunk_types.last().unwrap(), "IEND");
+        assert!(chunk_types[1..chunk_types.len() - 1]
+            .iter()
+            .all(|chunk_type| chunk_type == "IDAT"));
+        assert!(chunk_types.len() > 3);
+        assert!(png.chunks().iter().all(|chunk| chunk.length() <= 100));
+    }
+
+    #[test]
+    fn test_encode_invalid_images() {
+        let mut image = testing_image(ihdr::TRUECOLOR, 8, 3);
+        image.bit_depth = 4;
+        assert!(encode(&image, DEFAULT_IDAT_CHUNK_SIZE).is_err());
+
+        let mut image = testing_image(ihdr::TRUECOLOR, 8, 3);
+        image.width += 1;
+        assert!(encode(&image, DEFAULT_IDAT_CHUNK_SIZE).is_err());
+
+        let mut image = testing_image(ihdr::INDEXED, 2, 1);
+        image.palette = None;
+        assert!(encode(&image, DEFAULT_IDAT_CHUNK_SIZE).is_err());
+
+        let mut image = testing_image(ihdr::GREYSCALE, 1, 1);
+        image.samples = SampleBuffer::U8(vec![2; 13 * 7]);
+        assert!(encode(&image, DEFAULT_IDAT_CHUNK_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_adaptive_filter() {
+        // Steadily growing values are best predicted from the left neighbour
+        let row: Vec<u8> = (0..32).map(|value| value * 5).collect();
+        let (filter_type, filtered) = adaptive_filter(&row, None, 1);
+        assert_eq!(filter_type, filter::SUB);
+        assert!(filtered[1..].iter().all(|&byte| byte == 5));
+
+        // Row identical to the previous one is best predicted from above
+        let (filter_type, _) = adaptive_filter(&row, Some(&row), 1);
+        assert_eq!(filter_type, filter::UP);
+    }
+
+    #[test]
+    fn test_pack_row() {
+        assert_eq!(
+            pack_row(&[1, 0, 1, 1, 0, 0, 0, 1, 1], 1),
+            [0b1011_0001, 0b1000_0000]
+        );
+        assert_eq!(pack_row(&[2, 3, 0], 2), [0b1011_0000]);
+        assert_eq!(pack_row(&[11, 1], 4), [0b1011_0001]);
+    }
+}
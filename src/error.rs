@@ -0,0 +1,135 @@
+//! Errors of reading and writing png files, which callers may want to tell apart.
+
+use std::{fmt::Display, io};
+
+use crate::chunk::MAX_LENGTH;
+
+/// Failure of reading or writing png file.
+/// Library functions returning `anyhow::Error` keep it in the error chain, so it can be found with `downcast_ref`.
+#[derive(Debug)]
+pub enum PngError {
+    /// File doesn't start with png signature
+    InvalidSignature,
+    /// Field of chunk at given byte offset is cut short by the end of input,
+    /// or declared data length is larger than png specification allows
+    InvalidLength {
+        offset: u64,
+        field: &'static str,
+        expected: u64,
+    },
+    /// CRC stored in chunk at given byte offset doesn't match its content
+    CrcMismatch {
+        offset: u64,
+        chunk_type: String,
+        /// CRC computed from chunk type and data
+        expected: u32,
+        /// CRC stored in file
+        actual: u32,
+    },
+    /// Bytes which aren't four ascii letters (offset is known only when they were read from file)
+    InvalidChunkType {
+        offset: Option<u64>,
+        value: Vec<u8>,
+    },
+    /// File doesn't contain chunk with given type
+    /// (offset is known when the chunk is required at given position, like IHDR)
+    MissingChunk {
+        chunk_type: String,
+        offset: Option<u64>,
+    },
+    Io(io::Error),
+    Http(reqwest::Error),
+}
+
+impl PngError {
+    /// Process exit code used by the command line tool (1 is left for other errors)
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            PngError::InvalidSignature => 10,
+            PngError::InvalidLength { .. } => 11,
+            PngError::CrcMismatch { .. } => 12,
+            PngError::InvalidChunkType { .. } => 13,
+            PngError::MissingChunk { .. } => 14,
+            PngError::Io(_) => 15,
+            PngError::Http(_) => 16,
+        }
+    }
+}
+
+impl Display for PngError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PngError::InvalidSignature => write!(f, "Invalid png signature"),
+            PngError::InvalidLength {
+                offset, expected, ..
+            } if *expected > MAX_LENGTH as u64 => write!(
+                f,
+                "Chunk length {} exceeds the maximum of {} bytes (chunk at byte {})",
+                expected, MAX_LENGTH, offset
+            ),
+            PngError::InvalidLength {
+                offset,
+                field,
+                expected,
+            } => write!(
+                f,
+                "{} should be exactly {} bytes (chunk at byte {})",
+                field, expected, offset
+            ),
+            PngError::CrcMismatch {
+                offset,
+                chunk_type,
+                expected,
+                actual,
+            } => write!(
+                f,
+                "Invalid CRC of chunk {} at byte {} (expected {:#010x}, found {:#010x})",
+                chunk_type, offset, expected, actual
+            ),
+            PngError::InvalidChunkType { offset, value } => {
+                write!(
+                    f,
+                    "Invalid chunk type {:?}: must be 4 ascii letters",
+                    String::from_utf8_lossy(value)
+                )?;
+                match offset {
+                    Some(offset) => write!(f, " (chunk at byte {})", offset),
+                    None => Ok(()),
+                }
+            }
+            PngError::MissingChunk { chunk_type, offset } => match offset {
+                Some(offset) => write!(
+                    f,
+                    "Chunk with type {} is missing at byte {}",
+                    chunk_type, offset
+                ),
+                None => write!(f, "Chunk with type {} doesn't exist", chunk_type),
+            },
+            // Underlying error is returned as source
+            PngError::Io(_) => write!(f, "I/O error"),
+            PngError::Http(_) => write!(f, "HTTP error"),
+        }
+    }
+}
+
+impl std::error::Error for PngError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            PngError::Io(error) => Some(error),
+            PngError::Http(error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for PngError {
+    fn from(error: io::Error) -> Self {
+        PngError::Io(error)
+    }
+}
+
+impl From<reqwest::Error> for PngError {
+    fn from(error: reqwest::Error) -> Self {
+        PngError::Http(error)
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::{bail, Result};
+
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Filters.html
+pub const NONE: u8 = 0;
+pub const SUB: u8 = 1;
+pub const UP: u8 = 2;
+pub const AVERAGE: u8 = 3;
+pub const PAETH: u8 = 4;
+
+/// Reverses filtering of `height` rows, each consisting of filter type byte followed by `row_len` bytes.
+/// `bpp` is number of bytes per complete pixel (rounded up to 1).
+/// Returns unfiltered rows (without filter type bytes) and filter type used for every row.
+pub fn unfilter(
+    data: &[u8],
+    height: usize,
+    row_len: usize,
+    bpp: usize,
+) -> Result<(Vec<u8>, Vec<u8>)> {
+    if data.len() < height * (row_len + 1) {
+        bail!(
+            "Image data is too short: expected {} bytes, got {}",
+            height * (row_len + 1),
+            data.len()
+        );
+    }
+
+    let mut rows: Vec<u8> = vec![0; height * row_len];
+    let mut filter_types: Vec<u8> = Vec::with_capacity(height);
+    for y in 0..height {
+        let filtered = &data[y * (row_len + 1)..(y + 1) * (row_len + 1)];
+        let filter_type = filtered[0];
+        let (previous_rows, current_rows) = rows.split_at_mut(y * row_len);
+        let previous = match y {
+            0 => None,
+            _ => Some(&previous_rows[(y - 1) * row_len..]),
+        };
+        let current = &mut current_rows[..row_len];
+
+        for x in 0..row_len {
+            let left = if x >= bpp { current[x - bpp] } else { 0 };
+            let up = previous.map_or(0, |row| row[x]);
+            let up_left = if x >= bpp {
+                previous.map_or(0, |row| row[x - bpp])
+            } else {
+                0
+            };
+            let predictor = match filter_type {
+                NONE => 0,
+                SUB => left,
+                UP => up,
+                AVERAGE => ((left as u16 + up as u16) / 2) as u8,
+                PAETH => paeth(left, up, up_left),
+                _ => bail!("Invalid filter type {} in row {}", filter_type, y),
+            };
+            current[x] = filtered[x + 1].wrapping_add(predictor);
+        }
+        filter_types.push(filter_type);
+    }
+
+    Ok((rows, filter_types))
+}
+
+/// Filters rows with given filter types, producing data ready for compression
+pub fn filter(rows: &[u8], row_len: usize, bpp: usize, filter_types: &[u8]) -> Vec<u8> {
+    let height = filter_types.len();
+    let mut data: Vec<u8> = Vec::with_capacity(height * (row_len + 1));
+    for (y, filter_type) in filter_types.iter().enumerate() {
+        let previous = match y {
+            0 => None,
+            _ => Some(&rows[(y - 1) * row_len..y * row_len]),
+        };
+        data.push(*filter_type);
+        data.extend(filter_row(
+            &rows[y * row_len..(y + 1) * row_len],
+            previous,
+            bpp,
+            *filter_type,
+        ));
+    }
+    data
+}
+
+/// Applies single filter type to row, given previous row (None for the first row)
+pub fn filter_row(row: &[u8], previous: Option<&[u8]>, bpp: usize, filter_type: u8) -> Vec<u8> {
+    (0..row.len())
+        .map(|x| {
+            let left = if x >= bpp { row[x - bpp] } else { 0 };
+            let up = previous.map_or(0, |row| row[x]);
+            let up_left = if x >= bpp {
+                previous.map_or(0, |row| row[x - bpp])
+            } else {
+                0
+            };
+            let predictor = match filter_type {
+                SUB => left,
+                UP => up,
+                AVERAGE => ((left as u16 + up as u16) / 2) as u8,
+                PAETH => paeth(left, up, up_left),
+                _ => 0,
+            };
+            row[x].wrapping_sub(predictor)
+        })
+        .collect()
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let pa = (p - a as i16).abs();
+    let pb = (p - b as i16).abs();
+    let pc = (p - c as i16).abs();
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_rows() -> Vec<u8> {
+        (0..4 * 6).map(|value| (value * 37 % 251) as u8).collect()
+    }
+
+    #[test]
+    fn test_filter_unfilter_round_trip() {
+        let rows = testing_rows();
+        for filter_type in [NONE, SUB, UP, AVERAGE, PAETH] {
+            let filter_types = vec![filter_type; 4];
+            let data = filter(&rows, 6, 3, &filter_types);
+            let (unfiltered, actual_types) = unfilter(&data, 4, 6, 3).unwrap();
+            assert_eq!(unfiltered, rows);
+            assert_eq!(actual_types, filter_types);
+        }
+    }
+
+    #[test]
+    fn test_unfilter_mixed_filters() {
+        let rows = testing_rows();
+        let filter_types = [PAETH, SUB, AVERAGE, UP];
+        let data = filter(&rows, 6, 2, &filter_types);
+        assert_eq!(unfilter(&data, 4, 6, 2).unwrap().0, rows);
+    }
+
+    #[test]
+    fn test_unfilter_invalid_data() {
+        assert!(unfilter(&[0, 1, 2], 2, 2, 1).is_err());
+        assert!(unfilter(&[5, 1, 2], 1, 2, 1).is_err());
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 15), 15);
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(10, 20, 20), 10);
+    }
+}
@@ -0,0 +1,374 @@
+//! Splitting payloads across several chunks and putting them back together.
+
+use std::collections::BTreeSet;
+
+use anyhow::{bail, Error, Result};
+use chacha20poly1305::aead::{rand_core::RngCore, OsRng};
+
+use crate::{
+    chunk::{Chunk, CRC_32},
+    chunk_type::ChunkType,
+    png::Png,
+};
+
+// Layout of fragment chunk data:
+// magic (4) | version (1) | sequence id (4) | index (4) | total count (4) | body crc (4) | body
+//
+// All fragments of one payload share chunk type and sequence id.
+const MAGIC: [u8; 4] = [0xED, b'M', b'I', b'F'];
+const VERSION: u8 = 1;
+const FIELD_LEN: usize = 4;
+const HEADER_LEN: usize = MAGIC.len() + 1 + 4 * FIELD_LEN;
+/// Longest list of missing fragments shown in error
+const MAX_LISTED_MISSING: usize = 10;
+
+/// Payload reassembled from fragments
+pub struct Reassembled {
+    pub data: Vec<u8>,
+    /// Recoverable problems found during reassembly (duplicated or out-of-order fragments)
+    pub warnings: Vec<String>,
+}
+
+/// Fragments sharing sequence id, which form one payload
+pub struct Sequence {
+    pub sequence_id: u32,
+    /// Indices of chunks holding fragments of the sequence, in order of appearance in file
+    pub chunks: Vec<usize>,
+    /// Reassembled payload or reason why it couldn't be reassembled
+    pub result: Result<Reassembled>,
+}
+
+struct Fragment<'a> {
+    sequence_id: u32,
+    index: u32,
+    total: u32,
+    crc: u32,
+    body: &'a [u8],
+}
+
+/// Checks if data starts with fragment header
+pub fn is_fragment(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Splits data into fragments with at most `fragment_size` bytes of body each
+pub fn split(data: &[u8], fragment_size: usize) -> Result<Vec<Vec<u8>>> {
+    split_with_sequence_id(data, fragment_size, OsRng.next_u32())
+}
+
+fn split_with_sequence_id(
+    data: &[u8],
+    fragment_size: usize,
+    sequence_id: u32,
+) -> Result<Vec<Vec<u8>>> {
+    if fragment_size == 0 {
+        bail!("Fragment size must be greater than 0");
+    }
+    let total = u32::try_from(data.len().div_ceil(fragment_size).max(1))
+        .map_err(|_| Error::msg("Too many fragments, increase fragment size"))?;
+
+    let bodies: Vec<&[u8]> = if data.is_empty() {
+        vec![data]
+    } else {
+        data.chunks(fragment_size).collect()
+    };
+    let fragments = bodies
+        .into_iter()
+        .zip(0u32..)
+        .map(|(body, index)| {
+            let mut fragment: Vec<u8> = Vec::with_capacity(HEADER_LEN + body.len());
+            fragment.extend(MAGIC);
+            fragment.push(VERSION);
+            fragment.extend(sequence_id.to_be_bytes());
+            fragment.extend(index.to_be_bytes());
+            fragment.extend(total.to_be_bytes());
+            fragment.extend(CRC_32.checksum(body).to_be_bytes());
+            fragment.extend(body);
+            fragment
+        })
+        .collect();
+    Ok(fragments)
+}
+
+/// Reassembles payloads from fragments stored in chunks with given type.
+/// Fragments are grouped by sequence id and every sequence (in order of its first fragment)
+/// is reassembled separately, so that problems with one of them don't hide the others.
+pub fn reassemble(png: &Png, chunk_type: &ChunkType) -> Result<Vec<Sequence>> {
+    let mut groups: Vec<(u32, Vec<usize>, Vec<Fragment>)> = Vec::new();
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        if chunk.chunk_type() != chunk_type || !is_fragment(chunk.data()) {
+            continue;
+        }
+        let fragment = parse_fragment(chunk)
+            .map_err(|err| err.context(format!("Couldn't parse fragment in chunk #{}", index)))?;
+        match groups
+            .iter_mut()
+            .find(|(sequence_id, _, _)| *sequence_id == fragment.sequence_id)
+        {
+            Some((_, chunks, fragments)) => {
+                chunks.push(index);
+                fragments.push(fragment);
+            }
+            None => groups.push((fragment.sequence_id, vec![index], vec![fragment])),
+        }
+    }
+    if groups.is_empty() {
+        bail!("There are no fragments in {} chunks", chunk_type);
+    }
+    Ok(groups
+        .into_iter()
+        .map(|(sequence_id, chunks, fragments)| Sequence {
+            sequence_id,
+            chunks,
+            result: reassemble_sequence(&fragments),
+        })
+        .collect())
+}
+
+/// Joins bodies of fragments which belong to the same sequence
+fn reassemble_sequence(fragments: &[Fragment]) -> Result<Reassembled> {
+    let total = fragments[0].total;
+    // Total comes from untrusted data, so it can't be used for allocation before it's checked
+    if total as usize > fragments.len() {
+        let present: BTreeSet<u32> = fragments.iter().map(|fragment| fragment.index).collect();
+        return Err(missing_fragments(total, &present));
+    }
+
+    let mut slots: Vec<Option<&[u8]>> = vec![None; total as usize];
+    let mut warnings = Vec::new();
+    let mut out_of_order = false;
+    let mut last_index = None;
+    for fragment in fragments {
+        if fragment.total != total {
+            bail!(
+                "Fragment {} claims {} fragments in total, but first one claims {}",
+                fragment.index,
+                fragment.total,
+                total
+            );
+        }
+        if CRC_32.checksum(fragment.body) != fragment.crc {
+            bail!(
+                "Fragment {} is corrupted (invalid checksum)",
+                fragment.index
+            );
+        }
+        let slot = slots
+            .get_mut(fragment.index as usize)
+            .ok_or(Error::msg(format!(
+                "Fragment index {} is out of range (total {})",
+                fragment.index, total
+            )))?;
+        match slot {
+            Some(body) if *body == fragment.body => {
+                warnings.push(format!("Fragment {} is duplicated", fragment.index));
+            }
+            Some(_) => bail!(
+                "Fragment {} is duplicated with different content",
+                fragment.index
+            ),
+            None => *slot = Some(fragment.body),
+        }
+        if last_index.is_some_and(|last| fragment.index < last) {
+            out_of_order = true;
+        }
+        last_index = Some(fragment.index);
+    }
+    if out_of_order {
+        warnings.push(String::from("Fragments are out of order"));
+    }
+
+    if slots.iter().any(Option::is_none) {
+        let present = (0..total)
+            .filter(|&index| slots[index as usize].is_some())
+            .collect();
+        return Err(missing_fragments(total, &present));
+    }
+
+    let mut data = Vec::new();
+    for body in slots.into_iter().flatten() {
+        data.extend(body);
+    }
+    Ok(Reassembled { data, warnings })
+}
+
+/// Lists (at most `MAX_LISTED_MISSING`) indices of fragments which are not `present`
+fn missing_fragments(total: u32, present: &BTreeSet<u32>) -> Error {
+    let missing_count = total as usize - present.range(..total).count();
+    let mut missing: Vec<String> = (0..total)
+        .filter(|index| !present.contains(index))
+        .take(MAX_LISTED_MISSING)
+        .map(|index| index.to_string())
+        .collect();
+    if missing_count > MAX_LISTED_MISSING {
+        missing.push(String::from("..."));
+    }
+    Error::msg(format!(
+        "Missing {} of {} fragments: {}",
+        missing_count,
+        total,
+        missing.join(", ")
+    ))
+}
+
+fn parse_fragment(chunk: &Chunk) -> Result<Fragment<'_>> {
+    let data = chunk.data();
+    if data.len() < HEADER_LEN {
+        bail!("Fragment is too short");
+    }
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        bail!("Unsupported fragment version: {}", version);
+    }
+    let field = |number: usize| {
+        let start = MAGIC.len() + 1 + number * FIELD_LEN;
+        u32::from_be_bytes([
+            data[start],
+            data[start + 1],
+            data[start + 2],
+            data[start + 3],
+        ])
+    };
+    Ok(Fragment {
+        sequence_id: field(0),
+        index: field(1),
+        total: field(2),
+        crc: field(3),
+        body: &data[HEADER_LEN..],
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    const MESSAGE: &[u8] = b"This is where your secret message will be!";
+
+    fn fragment_chunks(data: &[u8], fragment_size: usize, sequence_id: u32) -> Vec<Chunk> {
+        split_with_sequence_id(data, fragment_size, sequence_id)
+            .unwrap()
+            .into_iter()
+            .map(|fragment| Chunk::new(ChunkType::from_str("ruSt").unwrap(), fragment))
+            .collect()
+    }
+
+    fn reassemble_all(chunks: Vec<Chunk>) -> Vec<Sequence> {
+        reassemble(
+            &Png::from_chunks(chunks),
+            &ChunkType::from_str("ruSt").unwrap(),
+        )
+        .unwrap()
+    }
+
+    /// Reassembles chunks holding single sequence
+    fn reassemble_chunks(chunks: Vec<Chunk>) -> Result<Reassembled> {
+        let mut sequences = reassemble_all(chunks);
+        assert_eq!(sequences.len(), 1);
+        sequences.remove(0).result
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let chunks = fragment_chunks(MESSAGE, 10, 1);
+        assert_eq!(chunks.len(), 5);
+        assert!(chunks.iter().all(|chunk| is_fragment(chunk.data())));
+
+        let reassembled = reassemble_chunks(chunks).unwrap();
+        assert_eq!(reassembled.data, MESSAGE);
+        assert!(reassembled.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_split_empty_data() {
+        let chunks = fragment_chunks(&[], 10, 1);
+        assert_eq!(chunks.len(), 1);
+        assert!(reassemble_chunks(chunks).unwrap().data.is_empty());
+    }
+
+    #[test]
+    fn test_reassemble_out_of_order_and_duplicated() {
+        let mut chunks = fragment_chunks(MESSAGE, 10, 1);
+        chunks.swap(1, 3);
+        chunks.push(Chunk::new(
+            ChunkType::from_str("ruSt").unwrap(),
+            chunks[2].data().to_vec(),
+        ));
+
+        let reassembled = reassemble_chunks(chunks).unwrap();
+        assert_eq!(reassembled.data, MESSAGE);
+        assert_eq!(reassembled.warnings.len(), 2);
+    }
+
+    #[test]
+    fn test_reassemble_missing_fragments() {
+        let mut chunks = fragment_chunks(MESSAGE, 10, 1);
+        chunks.remove(3);
+        chunks.remove(1);
+
+        let error = reassemble_chunks(chunks).err().unwrap();
+        assert_eq!(error.to_string(), "Missing 2 of 5 fragments: 1, 3");
+    }
+
+    #[test]
+    fn test_reassemble_interleaved_sequences() {
+        let mut first = fragment_chunks(MESSAGE, 10, 1).into_iter();
+        let mut second = fragment_chunks(b"Other message", 5, 2).into_iter();
+        let mut chunks = Vec::new();
+        while first.len() + second.len() > 0 {
+            chunks.extend(first.next());
+            chunks.extend(second.next());
+        }
+        chunks.push(Chunk::new(
+            ChunkType::from_str("otHr").unwrap(),
+            split_with_sequence_id(b"x", 1, 3).unwrap().remove(0),
+        ));
+        // Stale fragment of earlier message doesn't hide the others
+        chunks.insert(0, fragment_chunks(b"Stale message", 5, 4).remove(1));
+
+        let sequences = reassemble_all(chunks);
+        let ids: Vec<u32> = sequences
+            .iter()
+            .map(|sequence| sequence.sequence_id)
+            .collect();
+        assert_eq!(ids, [4, 1, 2]);
+        assert_eq!(sequences[0].chunks, [0]);
+        assert_eq!(
+            sequences[0].result.as_ref().err().unwrap().to_string(),
+            "Missing 2 of 3 fragments: 0, 2"
+        );
+        assert_eq!(sequences[1].chunks, [1, 3, 5, 7, 8]);
+        assert_eq!(sequences[1].result.as_ref().unwrap().data, MESSAGE);
+        assert_eq!(sequences[2].chunks, [2, 4, 6]);
+        let other = sequences[2].result.as_ref().unwrap();
+        assert_eq!(other.data, b"Other message");
+        assert!(other.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_reassemble_oversized_total() {
+        let mut fragment = split_with_sequence_id(MESSAGE, 100, 1).unwrap().remove(0);
+        let total_start = MAGIC.len() + 1 + 2 * FIELD_LEN;
+        fragment[total_start..total_start + FIELD_LEN].copy_from_slice(&u32::MAX.to_be_bytes());
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), fragment);
+
+        let error = reassemble_chunks(vec![chunk]).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Missing 4294967294 of 4294967295 fragments: 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, ..."
+        );
+    }
+
+    #[test]
+    fn test_reassemble_corrupted_fragment() {
+        let mut fragments = split_with_sequence_id(MESSAGE, 10, 1).unwrap();
+        let last = fragments[2].len() - 1;
+        fragments[2][last] ^= 1;
+        let chunks = fragments
+            .into_iter()
+            .map(|fragment| Chunk::new(ChunkType::from_str("ruSt").unwrap(), fragment))
+            .collect();
+
+        assert!(reassemble_chunks(chunks).is_err());
+    }
+}
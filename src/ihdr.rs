@@ -0,0 +1,218 @@
+//! Image header (IHDR chunk).
+
+use anyhow::{bail, Error, Result};
+
+use std::str::FromStr;
+
+use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.IHDR
+pub const IHDR_CHUNK_TYPE: &str = "IHDR";
+const IHDR_DATA_LEN: usize = 13;
+const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+pub const GREYSCALE: u8 = 0;
+pub const TRUECOLOR: u8 = 2;
+pub const INDEXED: u8 = 3;
+pub const GREYSCALE_ALPHA: u8 = 4;
+pub const TRUECOLOR_ALPHA: u8 = 6;
+
+/// Image header
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct Ihdr {
+    pub width: u32,
+    pub height: u32,
+    pub bit_depth: u8,
+    pub color_type: u8,
+    pub compression_method: u8,
+    pub filter_method: u8,
+    pub interlace_method: u8,
+}
+
+impl Ihdr {
+    /// Checks that every field has value allowed by the specification
+    pub fn validate(&self) -> Result<()> {
+        if self.width == 0 || self.width > MAX_DIMENSION {
+            bail!(
+                "Invalid IHDR width {}: must be between 1 and {}",
+                self.width,
+                MAX_DIMENSION
+            );
+        }
+        if self.height == 0 || self.height > MAX_DIMENSION {
+            bail!(
+                "Invalid IHDR height {}: must be between 1 and {}",
+                self.height,
+                MAX_DIMENSION
+            );
+        }
+        let allowed_bit_depths: &[u8] = match self.color_type {
+            GREYSCALE => &[1, 2, 4, 8, 16],
+            INDEXED => &[1, 2, 4, 8],
+            TRUECOLOR | GREYSCALE_ALPHA | TRUECOLOR_ALPHA => &[8, 16],
+            color_type => bail!(
+                "Invalid IHDR color type {}: must be one of 0, 2, 3, 4, 6",
+                color_type
+            ),
+        };
+        if !allowed_bit_depths.contains(&self.bit_depth) {
+            let allowed: Vec<String> = allowed_bit_depths
+                .iter()
+                .map(|bit_depth| bit_depth.to_string())
+                .collect();
+            bail!(
+                "Invalid IHDR bit depth {} for color type {}: must be one of {}",
+                self.bit_depth,
+                self.color_type,
+                allowed.join(", ")
+            );
+        }
+        if self.compression_method != 0 {
+            bail!(
+                "Invalid IHDR compression method {}: only 0 is defined",
+                self.compression_method
+            );
+        }
+        if self.filter_method != 0 {
+            bail!(
+                "Invalid IHDR filter method {}: only 0 is defined",
+                self.filter_method
+            );
+        }
+        if self.interlace_method > 1 {
+            bail!(
+                "Invalid IHDR interlace method {}: must be 0 or 1",
+                self.interlace_method
+            );
+        }
+        Ok(())
+    }
+
+    /// Number of samples in every pixel
+    pub fn channels(&self) -> Result<usize> {
+        channels(self.color_type)
+    }
+
+    /// Number of bytes in every complete pixel (rounded up to 1), used as filter offset
+    pub fn bytes_per_pixel(&self) -> Result<usize> {
+        Ok((self.channels()? * self.bit_depth as usize).div_ceil(8))
+    }
+
+    /// Number of bytes in unfiltered row of `width` pixels
+    pub fn row_len(&self, width: u32) -> Result<usize> {
+        let bits = (width as usize)
+            .checked_mul(self.channels()? * self.bit_depth as usize)
+            .ok_or_else(|| Error::msg(format!("Row of {} pixels is too long", width)))?;
+        Ok(bits.div_ceil(8))
+    }
+
+    /// Builds IHDR chunk
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data: Vec<u8> = Vec::with_capacity(IHDR_DATA_LEN);
+        data.extend(self.width.to_be_bytes());
+        data.extend(self.height.to_be_bytes());
+        data.extend([
+            self.bit_depth,
+            self.color_type,
+            self.compression_method,
+            self.filter_method,
+            self.interlace_method,
+        ]);
+        Ok(Chunk::new(ChunkType::from_str(IHDR_CHUNK_TYPE)?, data))
+    }
+}
+
+/// Number of samples in every pixel of given colour type
+pub fn channels(color_type: u8) -> Result<usize> {
+    match color_type {
+        GREYSCALE | INDEXED => Ok(1),
+        GREYSCALE_ALPHA => Ok(2),
+        TRUECOLOR => Ok(3),
+        TRUECOLOR_ALPHA => Ok(4),
+        color_type => bail!("Invalid color type: {}", color_type),
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type().to_string() != IHDR_CHUNK_TYPE {
+            bail!("Expected IHDR chunk, got {}", chunk.chunk_type());
+        }
+        let data = chunk.data();
+        if data.len() != IHDR_DATA_LEN {
+            bail!("IHDR chunk should be exactly {} bytes", IHDR_DATA_LEN);
+        }
+        let ihdr = Ihdr {
+            width: u32::from_be_bytes([data[0], data[1], data[2], data[3]]),
+            height: u32::from_be_bytes([data[4], data[5], data[6], data[7]]),
+            bit_depth: data[8],
+            color_type: data[9],
+            compression_method: data[10],
+            filter_method: data[11],
+            interlace_method: data[12],
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ihdr_from_chunk() {
+        let data = vec![0, 0, 0, 50, 0, 0, 1, 0, 8, 6, 0, 0, 1];
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data);
+        let ihdr = Ihdr::try_from(&chunk).unwrap();
+        assert_eq!(ihdr.width, 50);
+        assert_eq!(ihdr.height, 256);
+        assert_eq!(ihdr.bit_depth, 8);
+        assert_eq!(ihdr.color_type, TRUECOLOR_ALPHA);
+        assert_eq!(ihdr.interlace_method, 1);
+        assert_eq!(ihdr.bytes_per_pixel().unwrap(), 4);
+        assert_eq!(ihdr.row_len(50).unwrap(), 200);
+        assert_eq!(ihdr.to_chunk().unwrap().as_bytes(), chunk.as_bytes());
+    }
+
+    #[test]
+    fn test_invalid_ihdr_fields() {
+        let valid = [0, 0, 0, 50, 0, 0, 1, 0, 8, 6, 0, 0, 1];
+        for (index, value, field) in [
+            (3, 0, "width"),
+            (0, 0x80, "width"),
+            (6, 0, "height"),
+            (8, 4, "bit depth"),
+            (8, 3, "bit depth"),
+            (9, 5, "color type"),
+            (10, 1, "compression method"),
+            (11, 1, "filter method"),
+            (12, 2, "interlace method"),
+        ] {
+            let mut data = valid;
+            data[index] = value;
+            let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data.to_vec());
+            let error = Ihdr::try_from(&chunk).err().unwrap().to_string();
+            assert!(error.contains(field), "{}", error);
+        }
+
+        // Palette images can't have 16-bit samples
+        let data = vec![0, 0, 0, 1, 0, 0, 0, 1, 16, 3, 0, 0, 0];
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), data);
+        let error = Ihdr::try_from(&chunk).err().unwrap().to_string();
+        assert_eq!(
+            error,
+            "Invalid IHDR bit depth 16 for color type 3: must be one of 1, 2, 4, 8"
+        );
+    }
+
+    #[test]
+    fn test_invalid_ihdr_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("IHDR").unwrap(), vec![0; 12]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+        let chunk = Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![0; 13]);
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+}
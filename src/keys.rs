@@ -0,0 +1,104 @@
+//! Key files holding X25519 (encryption) and Ed25519 (signing) keys.
+
+use std::{fs, io::Write, path::Path};
+
+use anyhow::{bail, Context, Error, Result};
+
+// Key file consists of single line: <label> <key as hex>
+/// Label of recipient key, used to encrypt messages
+pub const X25519_PUBLIC_KEY_LABEL: &str = "EDMIPNG-X25519-PUBLIC-KEY";
+/// Label of identity key, used to decrypt messages
+pub const X25519_SECRET_KEY_LABEL: &str = "EDMIPNG-X25519-SECRET-KEY";
+/// Label of key used to verify signatures
+pub const ED25519_PUBLIC_KEY_LABEL: &str = "EDMIPNG-ED25519-PUBLIC-KEY";
+/// Label of key used to sign chunks
+pub const ED25519_SECRET_KEY_LABEL: &str = "EDMIPNG-ED25519-SECRET-KEY";
+
+const KEY_LEN: usize = 32;
+
+/// Reads 32 byte key stored under given label from file
+pub fn read_key<P: AsRef<Path>>(path: P, label: &str) -> Result<[u8; KEY_LEN]> {
+    let path = path.as_ref();
+    let content = fs::read_to_string(path)
+        .with_context(|| format!("Couldn't read key file {}", path.display()))?;
+    parse_key(&content, label).with_context(|| format!("Invalid key file {}", path.display()))
+}
+
+/// Writes key under given label to a new file (existing files are never overwritten).
+/// Secret keys are only readable by the owner.
+pub fn write_key<P: AsRef<Path>>(
+    path: P,
+    label: &str,
+    key: &[u8; KEY_LEN],
+    secret: bool,
+) -> Result<()> {
+    let path = path.as_ref();
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    if secret {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    #[cfg(not(unix))]
+    let _ = secret;
+    let mut file = options
+        .open(path)
+        .with_context(|| format!("Couldn't create key file {}", path.display()))?;
+    writeln!(file, "{}", format_key(key, label))
+        .with_context(|| format!("Couldn't write key file {}", path.display()))
+}
+
+/// Formats bytes as lowercase hex string
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn format_key(key: &[u8; KEY_LEN], label: &str) -> String {
+    format!("{} {}", label, to_hex(key))
+}
+
+fn parse_key(content: &str, label: &str) -> Result<[u8; KEY_LEN]> {
+    let (actual_label, hex) = content
+        .trim()
+        .split_once(' ')
+        .ok_or(Error::msg("Key file should contain label and key"))?;
+    if actual_label != label {
+        bail!("Expected {} but found {}", label, actual_label);
+    }
+    if hex.len() != KEY_LEN * 2 || !hex.is_ascii() {
+        bail!("Key should be exactly {} hex digits", KEY_LEN * 2);
+    }
+    let mut key = [0u8; KEY_LEN];
+    for (index, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[index * 2..index * 2 + 2], 16)
+            .context("Key contains invalid hex digit")?;
+    }
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_parse_key() {
+        let key: [u8; KEY_LEN] = core::array::from_fn(|index| index as u8 * 7);
+        let content = format_key(&key, X25519_PUBLIC_KEY_LABEL);
+        assert_eq!(parse_key(&content, X25519_PUBLIC_KEY_LABEL).unwrap(), key);
+    }
+
+    #[test]
+    fn test_parse_key_wrong_label() {
+        let content = format_key(&[1; KEY_LEN], X25519_PUBLIC_KEY_LABEL);
+        assert!(parse_key(&content, X25519_SECRET_KEY_LABEL).is_err());
+    }
+
+    #[test]
+    fn test_parse_invalid_key() {
+        let label = X25519_PUBLIC_KEY_LABEL;
+        assert!(parse_key("", label).is_err());
+        assert!(parse_key(&format!("{} abcd", label), label).is_err());
+        assert!(parse_key(&format!("{} {}", label, "zz".repeat(KEY_LEN)), label).is_err());
+    }
+}
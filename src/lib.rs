@@ -0,0 +1,63 @@
+//! Reading and editing chunks of png files.
+//!
+//! Files are parsed into [`Png`] (owned chunks, ready to be modified) or [`PngRef`]
+//! (chunks borrowing their data from the input buffer). Large files can be processed
+//! chunk by chunk with [`stream::ChunkReader`] and [`stream::ChunkWriter`].
+//!
+//! Message chunks created by the command line tool are built from the same parts:
+//! [`payload`] envelopes (compression, embedded files), [`crypto`] envelopes (encryption),
+//! [`fragment`] headers (messages split across chunks) and [`signature`] chunks.
+//! Files can be checked with [`validation`] and messages can be hidden in pixels with [`lsb`].
+//!
+//! ```
+//! use std::str::FromStr;
+//!
+//! use edmipng::{encoder, decoder::{Image, SampleBuffer}, Chunk, ChunkPosition, ChunkType, Png};
+//!
+//! # fn main() -> anyhow::Result<()> {
+//! let image = Image {
+//!     width: 2,
+//!     height: 1,
+//!     bit_depth: 8,
+//!     color_type: edmipng::ihdr::GREYSCALE,
+//!     samples: SampleBuffer::U8(vec![0, 255]),
+//!     palette: None,
+//! };
+//! let mut png = encoder::encode(&image, encoder::DEFAULT_IDAT_CHUNK_SIZE)?;
+//! let chunk = Chunk::new(ChunkType::from_str("ruSt")?, b"secret".to_vec());
+//! png.insert_chunk(chunk, ChunkPosition::BeforeIend)?;
+//!
+//! let parsed = Png::try_from(png.as_bytes().as_slice())?;
+//! assert_eq!(parsed.chunk_by_type("ruSt").unwrap().data(), b"secret");
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod chunk;
+pub mod chunk_type;
+pub mod crypto;
+pub mod decoder;
+pub mod encoder;
+pub mod error;
+pub mod fragment;
+pub mod ihdr;
+pub mod keys;
+pub mod lsb;
+pub mod manifest;
+pub mod payload;
+pub mod png;
+pub mod record;
+pub mod registry;
+pub mod signature;
+pub mod source;
+pub mod stream;
+pub mod text;
+pub mod validation;
+
+mod filter;
+
+pub use chunk::{Chunk, ChunkRef};
+pub use chunk_type::ChunkType;
+pub use error::PngError;
+pub use png::{ChunkPosition, Png, PngRef};
+pub use source::Source;
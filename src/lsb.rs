@@ -0,0 +1,240 @@
+//! Hiding messages in the least significant bits of image samples.
+
+use std::io::Write;
+
+use anyhow::{bail, Context, Result};
+use flate2::{write::ZlibEncoder, Compression};
+
+use crate::{
+    decoder::{self, SampleBuffer},
+    filter,
+    ihdr::{self, Ihdr},
+    png::Png,
+};
+
+// Message is hidden in the least significant bits of colour samples (alpha is left untouched),
+// one bit per sample, most significant bit of every byte first.
+// Layout of hidden data:
+// magic (4) | message length (4) | message
+const MAGIC: [u8; 4] = [0xED, b'M', b'I', b'L'];
+const HEADER_LEN: usize = MAGIC.len() + 4;
+
+const IDAT_CHUNK_SIZE: usize = 64 * 1024;
+
+/// Decoded 8-bit image samples together with data needed to encode them back
+struct Samples {
+    ihdr: Ihdr,
+    rows: Vec<u8>,
+    filter_types: Vec<u8>,
+}
+
+impl Samples {
+    fn from_png(png: &Png) -> Result<Samples> {
+        let ihdr = png.ihdr()?;
+        check_supported(ihdr.bit_depth, ihdr.color_type)?;
+        if ihdr.interlace_method != 0 {
+            bail!("LSB carrier doesn't support interlaced images");
+        }
+
+        let height = ihdr.height as usize;
+        let row_len = ihdr.row_len(ihdr.width)?;
+        // Sizes come from IHDR, so they can overflow on 32-bit targets
+        let data_len = row_len
+            .checked_add(1)
+            .and_then(|len| len.checked_mul(height))
+            .with_context(|| format!("Image is too large: {}x{}", ihdr.width, ihdr.height))?;
+        let data = decoder::inflate_image_data(png, data_len)?;
+        let (rows, filter_types) =
+            filter::unfilter(&data, height, row_len, ihdr.bytes_per_pixel()?)?;
+
+        Ok(Samples {
+            ihdr,
+            rows,
+            filter_types,
+        })
+    }
+
+    fn write_to(&self, png: &mut Png) -> Result<()> {
+        let row_len = self.ihdr.row_len(self.ihdr.width)?;
+        let data = filter::filter(
+            &self.rows,
+            row_len,
+            self.ihdr.bytes_per_pixel()?,
+            &self.filter_types,
+        );
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+        encoder
+            .write_all(&data)
+            .context("Couldn't compress image data")?;
+        let compressed = encoder.finish().context("Couldn't compress image data")?;
+        png.set_image_data(&compressed, IDAT_CHUNK_SIZE)
+    }
+}
+
+fn check_supported(bit_depth: u8, color_type: u8) -> Result<()> {
+    if bit_depth != 8 {
+        bail!(
+            "LSB carrier supports only 8-bit images, this one has bit depth {}",
+            bit_depth
+        );
+    }
+    if color_type == ihdr::INDEXED {
+        bail!("LSB carrier doesn't support indexed-colour images");
+    }
+    Ok(())
+}
+
+/// Indices of colour samples (every sample except alpha) among `len` samples
+fn color_sample_indices(len: usize, color_type: u8) -> Result<impl Iterator<Item = usize>> {
+    let channels = ihdr::channels(color_type)?;
+    let color_channels = match color_type {
+        ihdr::GREYSCALE_ALPHA | ihdr::TRUECOLOR_ALPHA => channels - 1,
+        _ => channels,
+    };
+    Ok((0..len).filter(move |index| index % channels < color_channels))
+}
+
+/// Number of message bytes that can be hidden among `len` samples
+fn capacity(len: usize, color_type: u8) -> Result<usize> {
+    Ok((color_sample_indices(len, color_type)?.count() / 8).saturating_sub(HEADER_LEN))
+}
+
+/// Hides message in the least significant bits of image samples
+pub fn embed(png: &mut Png, message: &[u8]) -> Result<()> {
+    let mut samples = Samples::from_png(png)?;
+    let color_type = samples.ihdr.color_type;
+    let capacity = capacity(samples.rows.len(), color_type)?;
+    if message.len() > capacity {
+        bail!(
+            "Message is too long for this image: {} bytes, but only {} bytes can be hidden",
+            message.len(),
+            capacity
+        );
+    }
+    let length = u32::try_from(message.len()).context("Message is too long")?;
+
+    let hidden: Vec<u8> = MAGIC
+        .iter()
+        .chain(length.to_be_bytes().iter())
+        .chain(message)
+        .copied()
+        .collect();
+    let bits = hidden
+        .iter()
+        .flat_map(|byte| (0..8).rev().map(move |shift| (byte >> shift) & 1));
+    let indices = color_sample_indices(samples.rows.len(), color_type)?;
+    for (index, bit) in indices.zip(bits) {
+        samples.rows[index] = (samples.rows[index] & !1) | bit;
+    }
+
+    samples.write_to(png)
+}
+
+/// Extracts message hidden with `embed`
+pub fn extract(png: &Png) -> Result<Vec<u8>> {
+    let image = png.decode_image()?;
+    check_supported(image.bit_depth, image.color_type)?;
+    let SampleBuffer::U8(samples) = image.samples else {
+        bail!("LSB carrier supports only 8-bit images");
+    };
+    let mut bits =
+        color_sample_indices(samples.len(), image.color_type)?.map(|index| samples[index] & 1);
+    let mut read_bytes = |count: usize| -> Vec<u8> {
+        (0..count)
+            .map(|_| bits.by_ref().take(8).fold(0, |byte, bit| (byte << 1) | bit))
+            .collect()
+    };
+
+    if read_bytes(MAGIC.len()) != MAGIC {
+        bail!("Image doesn't contain LSB message");
+    }
+    let length_bytes = read_bytes(4);
+    let length = u32::from_be_bytes([
+        length_bytes[0],
+        length_bytes[1],
+        length_bytes[2],
+        length_bytes[3],
+    ]) as usize;
+    if length > capacity(samples.len(), image.color_type)? {
+        bail!("Hidden message length exceeds image capacity");
+    }
+    Ok(read_bytes(length))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+    use std::str::FromStr;
+
+    fn chunk(chunk_type: &str, data: Vec<u8>) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn testing_image(color_type: u8, channels: usize, bit_depth: u8, interlace: u8) -> Png {
+        let (width, height) = (32u32, 24u32);
+        let mut ihdr: Vec<u8> = Vec::new();
+        ihdr.extend(width.to_be_bytes());
+        ihdr.extend(height.to_be_bytes());
+        ihdr.extend([bit_depth, color_type, 0, 0, interlace]);
+
+        let row_len = width as usize * channels;
+        let rows: Vec<u8> = (0..height as usize * row_len)
+            .map(|value| (value * 31 % 256) as u8)
+            .collect();
+        let filter_types: Vec<u8> = (0..height as u8).map(|y| y % 5).collect();
+        let data = filter::filter(&rows, row_len, channels, &filter_types);
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(&data).unwrap();
+
+        Png::from_chunks(vec![
+            chunk("IHDR", ihdr),
+            chunk("IDAT", encoder.finish().unwrap()),
+            chunk("IEND", vec![]),
+        ])
+    }
+
+    #[test]
+    fn test_embed_extract() {
+        for (color_type, channels) in [(0, 1), (2, 3), (4, 2), (6, 4)] {
+            let mut png = testing_image(color_type, channels, 8, 0);
+            let original = Samples::from_png(&png).unwrap();
+            let message = b"Hidden in plain sight";
+            embed(&mut png, message).unwrap();
+            assert_eq!(extract(&png).unwrap(), message);
+
+            // Only least significant bits of colour samples were changed
+            let modified = Samples::from_png(&png).unwrap();
+            for (index, (before, after)) in original.rows.iter().zip(&modified.rows).enumerate() {
+                assert!(before ^ after <= 1);
+                if color_type >= 4 && index % channels == channels - 1 {
+                    assert_eq!(before, after);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_capacity() {
+        // 32 * 24 pixels * 3 colour samples = 2304 bits = 288 bytes minus header
+        let mut png = testing_image(6, 4, 8, 0);
+        let samples = Samples::from_png(&png).unwrap();
+        let capacity = capacity(samples.rows.len(), samples.ihdr.color_type).unwrap();
+        assert_eq!(capacity, 288 - HEADER_LEN);
+        assert!(embed(&mut png, &[0; 288 - HEADER_LEN + 1]).is_err());
+        assert!(embed(&mut png, &[0; 288 - HEADER_LEN]).is_ok());
+    }
+
+    #[test]
+    fn test_extract_without_message() {
+        let png = testing_image(2, 3, 8, 0);
+        assert!(extract(&png).is_err());
+    }
+
+    #[test]
+    fn test_unsupported_images() {
+        assert!(Samples::from_png(&testing_image(2, 6, 16, 0)).is_err());
+        assert!(Samples::from_png(&testing_image(3, 1, 8, 0)).is_err());
+        assert!(Samples::from_png(&testing_image(2, 3, 8, 1)).is_err());
+    }
+}
@@ -1,21 +1,51 @@
+use std::process::ExitCode;
+
 use anyhow::Result;
 use args::EdmiArgs;
 use clap::Parser;
+use edmipng::PngError;
 
 mod args;
-mod chunk;
-mod chunk_type;
 mod commands;
-mod png;
-mod source;
+mod output;
 
-fn main() -> Result<()> {
-    let args = EdmiArgs::parse();
+fn main() -> ExitCode {
+    match run(EdmiArgs::parse()) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("Error: {:?}", error);
+            // Errors of png handling get their own codes, everything else exits with 1
+            let code = error
+                .chain()
+                .find_map(|cause| cause.downcast_ref::<PngError>())
+                .map_or(1, PngError::exit_code);
+            ExitCode::from(code)
+        }
+    }
+}
 
+fn run(args: EdmiArgs) -> Result<()> {
     match args.command {
         args::Command::Encode(encode_args) => commands::encode(encode_args),
         args::Command::Decode(decode_args) => commands::decode(decode_args),
         args::Command::Remove(remove_args) => commands::remove(remove_args),
+        args::Command::Update(update_args) => commands::update(update_args),
         args::Command::Print(print_args) => commands::print(print_args),
+        args::Command::Inspect(inspect_args) => commands::inspect(inspect_args),
+        args::Command::Dump(dump_args) => commands::dump(dump_args),
+        args::Command::Extract(extract_args) => commands::extract(extract_args),
+        args::Command::Export(export_args) => commands::export(export_args),
+        args::Command::Pack(pack_args) => commands::pack(pack_args),
+        args::Command::Keygen(keygen_args) => commands::keygen(keygen_args),
+        args::Command::Verify(verify_args) => commands::verify(verify_args),
+        args::Command::Text(text_args) => match text_args.command {
+            args::TextCommand::List(list_args) => commands::text_list(list_args),
+            args::TextCommand::Get(get_args) => commands::text_get(get_args),
+            args::TextCommand::Set(set_args) => commands::text_set(set_args),
+            args::TextCommand::Remove(remove_args) => commands::text_remove(remove_args),
+        },
+        args::Command::Create(create_args) => commands::create(create_args),
+        args::Command::Validate(validate_args) => commands::validate(validate_args),
+        args::Command::Repair(repair_args) => commands::repair(repair_args),
     }
 }
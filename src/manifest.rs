@@ -0,0 +1,213 @@
+//! Png files split into separate chunk data files, described by a JSON or TOML manifest.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    error::PngError,
+    png::{Png, PngRef},
+};
+
+/// Name of file with bytes stored after the last chunk
+pub const TRAILING_DATA_FILE_NAME: &str = "trailing.bin";
+
+/// List of chunks (in order in which they appear in file) and files holding their data
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Manifest {
+    /// File with bytes stored after the last chunk, if there are any
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trailing_data: Option<PathBuf>,
+    pub chunks: Vec<ManifestEntry>,
+}
+
+/// Single chunk of the manifest
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub chunk_type: ChunkType,
+    /// File with chunk data, relative paths start in the manifest directory
+    pub file: PathBuf,
+}
+
+impl Manifest {
+    /// Describes chunks of the file, with data files named after index and type of chunks
+    pub fn from_png(png: &PngRef) -> Manifest {
+        let chunks = png
+            .chunks()
+            .iter()
+            .enumerate()
+            .map(|(index, chunk)| ManifestEntry {
+                chunk_type: chunk.chunk_type().clone(),
+                file: PathBuf::from(chunk_file_name(index, chunk.chunk_type())),
+            })
+            .collect();
+        let trailing_data =
+            (!png.trailing_data().is_empty()).then(|| PathBuf::from(TRAILING_DATA_FILE_NAME));
+        Manifest {
+            trailing_data,
+            chunks,
+        }
+    }
+
+    /// Reads manifest from file, as TOML if it has `.toml` extension and as JSON otherwise
+    pub fn from_file<P: AsRef<Path>>(path: P) -> Result<Manifest> {
+        let path = path.as_ref();
+        let text = fs::read_to_string(path)
+            .map_err(PngError::Io)
+            .context("Couldn't load manifest.")?;
+        if path
+            .extension()
+            .is_some_and(|extension| extension == "toml")
+        {
+            Manifest::from_toml(&text)
+        } else {
+            Manifest::from_json(&text)
+        }
+    }
+
+    pub fn from_json(text: &str) -> Result<Manifest> {
+        serde_json::from_str(text).context("Couldn't parse JSON manifest.")
+    }
+
+    pub fn from_toml(text: &str) -> Result<Manifest> {
+        toml::from_str(text).context("Couldn't parse TOML manifest.")
+    }
+
+    pub fn to_json(&self) -> Result<String> {
+        serde_json::to_string_pretty(self).context("Couldn't serialize manifest.")
+    }
+
+    pub fn to_toml(&self) -> Result<String> {
+        toml::to_string(self).context("Couldn't serialize manifest.")
+    }
+
+    /// Builds png from data files (relative paths start in `dir`), computing lengths and CRCs of chunks.
+    /// Order of chunks is taken as it is, so the result can break rules of png structure.
+    pub fn pack<P: AsRef<Path>>(&self, dir: P) -> Result<Png> {
+        let read = |file: &Path| {
+            let path = dir.as_ref().join(file);
+            fs::read(&path)
+                .map_err(PngError::Io)
+                .with_context(|| format!("Couldn't load {}", path.display()))
+        };
+
+        let chunks = self
+            .chunks
+            .iter()
+            .map(|entry| Chunk::try_new(entry.chunk_type.clone(), read(&entry.file)?))
+            .collect::<Result<Vec<Chunk>>>()?;
+        let mut png = Png::from_chunks(chunks);
+        if let Some(file) = &self.trailing_data {
+            png.set_trailing_data(read(file)?);
+        }
+        Ok(png)
+    }
+}
+
+/// Name of file with chunk data, e.g. `003-ruSt.bin`
+pub fn chunk_file_name(index: usize, chunk_type: &ChunkType) -> String {
+    format!("{:03}-{}.bin", index, chunk_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::png::ChunkPosition;
+    use std::{env, str::FromStr};
+
+    fn testing_png() -> Png {
+        let ihdr = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]);
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hello".to_vec());
+        png.insert_chunk(chunk, ChunkPosition::BeforeIend).unwrap();
+        png.set_trailing_data(b"junk".to_vec());
+        png
+    }
+
+    #[test]
+    fn test_manifest_from_png() {
+        let bytes = testing_png().as_bytes();
+        let manifest = Manifest::from_png(&PngRef::parse(&bytes).unwrap());
+        let files: Vec<&Path> = manifest
+            .chunks
+            .iter()
+            .map(|entry| entry.file.as_path())
+            .collect();
+        assert_eq!(
+            files,
+            [
+                Path::new("000-IHDR.bin"),
+                Path::new("001-ruSt.bin"),
+                Path::new("002-IEND.bin")
+            ]
+        );
+        assert_eq!(manifest.chunks[1].chunk_type.to_string(), "ruSt");
+        assert_eq!(
+            manifest.trailing_data.as_deref(),
+            Some(Path::new(TRAILING_DATA_FILE_NAME))
+        );
+    }
+
+    #[test]
+    fn test_manifest_formats() {
+        let bytes = testing_png().as_bytes();
+        let manifest = Manifest::from_png(&PngRef::parse(&bytes).unwrap());
+
+        let json = manifest.to_json().unwrap();
+        assert_eq!(Manifest::from_json(&json).unwrap(), manifest);
+        let toml = manifest.to_toml().unwrap();
+        assert_eq!(Manifest::from_toml(&toml).unwrap(), manifest);
+
+        let manifest =
+            Manifest::from_toml("[[chunks]]\nchunk_type = \"IEND\"\nfile = \"end.bin\"\n").unwrap();
+        assert_eq!(manifest.trailing_data, None);
+        assert_eq!(manifest.chunks[0].file, Path::new("end.bin"));
+        assert!(
+            Manifest::from_json(r#"{"chunks": [{"chunk_type": "ru1t", "file": "x"}]}"#).is_err()
+        );
+    }
+
+    #[test]
+    fn test_pack() {
+        let png = testing_png();
+        let bytes = png.as_bytes();
+        let png_ref = PngRef::parse(&bytes).unwrap();
+        let manifest = Manifest::from_png(&png_ref);
+
+        let dir = env::temp_dir().join(format!("edmipng-manifest-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        for (entry, chunk) in manifest.chunks.iter().zip(png_ref.chunks()) {
+            fs::write(dir.join(&entry.file), chunk.data()).unwrap();
+        }
+        fs::write(dir.join(TRAILING_DATA_FILE_NAME), b"junk").unwrap();
+        // Edited data gets new length and CRC
+        fs::write(dir.join("001-ruSt.bin"), b"hello, world").unwrap();
+
+        let packed = manifest.pack(&dir).unwrap();
+        let message = packed.chunk_by_type("ruSt").unwrap();
+        assert_eq!(message.length(), 12);
+        assert_eq!(
+            message.crc(),
+            Chunk::new(
+                ChunkType::from_str("ruSt").unwrap(),
+                b"hello, world".to_vec()
+            )
+            .crc()
+        );
+        assert_eq!(packed.trailing_data(), b"junk");
+        assert!(Png::try_from(packed.as_bytes().as_slice()).is_ok());
+
+        fs::remove_file(dir.join("002-IEND.bin")).unwrap();
+        assert!(manifest.pack(&dir).is_err());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,123 @@
+use anyhow::{bail, Context, Result};
+use serde::Serialize;
+
+use crate::args::OutputFormat;
+
+/// Longest value printed inside table cell, longer ones are cut
+const MAX_CELL_WIDTH: usize = 48;
+/// Number of bytes in every hexdump line
+const HEXDUMP_WIDTH: usize = 16;
+
+/// Prints value as JSON or YAML document
+pub fn print_document<T: Serialize>(value: &T, format: OutputFormat) -> Result<()> {
+    let document = match format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(value).context("Couldn't serialize output")?
+        }
+        OutputFormat::Yaml => {
+            serde_norway::to_string(value).context("Couldn't serialize output")?
+        }
+        _ => bail!("{:?} is not a document format", format),
+    };
+    println!("{}", document.trim_end());
+    Ok(())
+}
+
+/// Prints rows with columns aligned under headers
+pub fn print_table(headers: &[&str], rows: &[Vec<String>]) {
+    let rows: Vec<Vec<String>> = rows
+        .iter()
+        .map(|row| row.iter().map(|cell| shorten(cell)).collect())
+        .collect();
+    let widths: Vec<usize> = headers
+        .iter()
+        .enumerate()
+        .map(|(column, header)| {
+            rows.iter()
+                .filter_map(|row| row.get(column))
+                .map(|cell| cell.chars().count())
+                .chain([header.len()])
+                .max()
+                .unwrap_or_default()
+        })
+        .collect();
+
+    let headers: Vec<String> = headers.iter().map(|header| header.to_string()).collect();
+    for row in [&headers].into_iter().chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+/// Formats bytes as lines of offset, hex values and printable ascii characters,
+/// where `offset` is position of the first byte in file
+pub fn hexdump(bytes: &[u8], offset: u64) -> String {
+    let mut dump = String::new();
+    for (line, chunk) in bytes.chunks(HEXDUMP_WIDTH).enumerate() {
+        let hex: Vec<String> = (0..HEXDUMP_WIDTH)
+            .map(|index| match chunk.get(index) {
+                Some(byte) => format!("{:02x}", byte),
+                None => String::from("  "),
+            })
+            .collect();
+        let ascii: String = chunk
+            .iter()
+            .map(|&byte| match byte {
+                0x20..=0x7e => byte as char,
+                _ => '.',
+            })
+            .collect();
+        let (first_half, second_half) = hex.split_at(HEXDUMP_WIDTH / 2);
+        dump.push_str(&format!(
+            "{:08x}  {}  {}  |{}|\n",
+            offset + (line * HEXDUMP_WIDTH) as u64,
+            first_half.join(" "),
+            second_half.join(" "),
+            ascii
+        ));
+    }
+    dump
+}
+
+/// Replaces control characters (so that every row stays in one line) and cuts long values
+fn shorten(cell: &str) -> String {
+    let cell: String = cell
+        .chars()
+        .map(|c| if c.is_control() { ' ' } else { c })
+        .collect();
+    if cell.chars().count() <= MAX_CELL_WIDTH {
+        return cell;
+    }
+    let mut shortened: String = cell.chars().take(MAX_CELL_WIDTH - 3).collect();
+    shortened.push_str("...");
+    shortened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_hexdump() {
+        let dump = hexdump(b"ruSthello, world!\n", 0x21);
+        assert_eq!(
+            dump,
+            "00000021  72 75 53 74 68 65 6c 6c  6f 2c 20 77 6f 72 6c 64  |ruSthello, world|\n\
+             00000031  21 0a                                             |!.|\n"
+        );
+        assert_eq!(hexdump(b"", 0), "");
+    }
+
+    #[test]
+    fn test_shorten() {
+        assert_eq!(shorten("short\nvalue"), "short value");
+        let long = "x".repeat(100);
+        let shortened = shorten(&long);
+        assert_eq!(shortened.len(), MAX_CELL_WIDTH);
+        assert!(shortened.ends_with("..."));
+    }
+}
@@ -0,0 +1,243 @@
+//! Envelope of chunk data, marking compressed messages and describing embedded files.
+
+use std::io::{Read, Write};
+
+use anyhow::{bail, Context, Error, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+// Layout of wrapped payload (before optional encryption):
+// magic (4) | version (1) | flags (1) | [metadata] | body
+//
+// Metadata (present if METADATA_FLAG is set):
+// file name length (2) | file name (utf-8) | mime type length (2) | mime type (utf-8)
+//
+// Body is compressed if COMPRESSED_FLAG is set. Metadata is never compressed.
+//
+// Data without magic is treated as raw message, so chunks created
+// without any payload options stay readable by other tools.
+const MAGIC: [u8; 4] = [0xED, b'M', b'I', b'D'];
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = MAGIC.len() + 2;
+const FIELD_LENGTH_LEN: usize = 2;
+
+const COMPRESSED_FLAG: u8 = 0b0000_0001;
+const METADATA_FLAG: u8 = 0b0000_0010;
+const KNOWN_FLAGS: u8 = COMPRESSED_FLAG | METADATA_FLAG;
+
+/// Default limit of decompressed payload size (64 MiB)
+pub const DEFAULT_MAX_SIZE: u64 = 64 * 1024 * 1024;
+
+/// Describes embedded file
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct FileMetadata {
+    /// Original file name (empty if unknown, e.g. for data read from stdin)
+    pub file_name: String,
+    pub mime_type: String,
+}
+
+/// Message together with optional description of the file it came from
+#[derive(Debug, PartialEq, Eq)]
+pub struct Payload {
+    pub content: Vec<u8>,
+    pub metadata: Option<FileMetadata>,
+}
+
+/// Checks if data starts with payload envelope header
+pub fn is_wrapped(data: &[u8]) -> bool {
+    data.starts_with(&MAGIC)
+}
+
+/// Wraps payload inside envelope, optionally compressing its content with deflate
+pub fn wrap(payload: &Payload, compress: bool) -> Result<Vec<u8>> {
+    let mut flags = 0;
+    if compress {
+        flags |= COMPRESSED_FLAG;
+    }
+    if payload.metadata.is_some() {
+        flags |= METADATA_FLAG;
+    }
+
+    let mut data: Vec<u8> = Vec::with_capacity(HEADER_LEN + payload.content.len());
+    data.extend(MAGIC);
+    data.push(VERSION);
+    data.push(flags);
+    if let Some(metadata) = &payload.metadata {
+        write_field(&mut data, &metadata.file_name).context("Invalid file name")?;
+        write_field(&mut data, &metadata.mime_type).context("Invalid mime type")?;
+    }
+    if compress {
+        let mut encoder = ZlibEncoder::new(data, Compression::best());
+        encoder
+            .write_all(&payload.content)
+            .context("Couldn't compress message")?;
+        data = encoder.finish().context("Couldn't compress message")?;
+    } else {
+        data.extend(&payload.content);
+    }
+    Ok(data)
+}
+
+/// Extracts payload from envelope, refusing to inflate more than `max_size` bytes.
+/// Data which is not wrapped is returned as is.
+pub fn unwrap(data: &[u8], max_size: u64) -> Result<Payload> {
+    if !is_wrapped(data) {
+        return Ok(Payload {
+            content: data.to_vec(),
+            metadata: None,
+        });
+    }
+    if data.len() < HEADER_LEN {
+        bail!("Payload is too short");
+    }
+    let version = data[MAGIC.len()];
+    if version != VERSION {
+        bail!("Unsupported payload version: {}", version);
+    }
+    let flags = data[MAGIC.len() + 1];
+    if flags & !KNOWN_FLAGS != 0 {
+        bail!("Unsupported payload flags: {:#010b}", flags);
+    }
+
+    let mut body = &data[HEADER_LEN..];
+    let metadata = if flags & METADATA_FLAG != 0 {
+        let file_name = read_field(&mut body).context("Invalid file name")?;
+        let mime_type = read_field(&mut body).context("Invalid mime type")?;
+        Some(FileMetadata {
+            file_name,
+            mime_type,
+        })
+    } else {
+        None
+    };
+
+    if flags & COMPRESSED_FLAG == 0 {
+        return Ok(Payload {
+            content: body.to_vec(),
+            metadata,
+        });
+    }
+
+    let mut content = Vec::new();
+    ZlibDecoder::new(body)
+        .take(max_size.saturating_add(1))
+        .read_to_end(&mut content)
+        .context("Couldn't decompress message")?;
+    if content.len() as u64 > max_size {
+        bail!(
+            "Decompressed message is larger than allowed {} bytes",
+            max_size
+        );
+    }
+    Ok(Payload { content, metadata })
+}
+
+fn write_field(data: &mut Vec<u8>, field: &str) -> Result<()> {
+    let length = u16::try_from(field.len())
+        .map_err(|_| Error::msg(format!("Field can't be longer than {} bytes", u16::MAX)))?;
+    data.extend(length.to_be_bytes());
+    data.extend(field.as_bytes());
+    Ok(())
+}
+
+fn read_field(data: &mut &[u8]) -> Result<String> {
+    if data.len() < FIELD_LENGTH_LEN {
+        bail!("Payload is too short");
+    }
+    let (length, rest) = data.split_at(FIELD_LENGTH_LEN);
+    let length = u16::from_be_bytes(length.try_into()?) as usize;
+    if rest.len() < length {
+        bail!("Payload is too short");
+    }
+    let (field, rest) = rest.split_at(length);
+    *data = rest;
+    String::from_utf8(field.to_vec()).context("Field is not valid utf-8")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MESSAGE: &[u8] = b"This is where your secret message will be!";
+
+    fn message_payload() -> Payload {
+        Payload {
+            content: MESSAGE.to_vec(),
+            metadata: None,
+        }
+    }
+
+    #[test]
+    fn test_wrap_unwrap() {
+        for compress in [false, true] {
+            let data = wrap(&message_payload(), compress).unwrap();
+            assert!(is_wrapped(&data));
+            assert_eq!(unwrap(&data, DEFAULT_MAX_SIZE).unwrap(), message_payload());
+        }
+    }
+
+    #[test]
+    fn test_wrap_unwrap_with_metadata() {
+        let payload = Payload {
+            content: vec![0, 159, 146, 150, 255],
+            metadata: Some(FileMetadata {
+                file_name: String::from("archive.tar.gz"),
+                mime_type: String::from("application/gzip"),
+            }),
+        };
+        for compress in [false, true] {
+            let data = wrap(&payload, compress).unwrap();
+            assert_eq!(unwrap(&data, DEFAULT_MAX_SIZE).unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_compression_shrinks_payload() {
+        let payload = Payload {
+            content: MESSAGE.repeat(100),
+            metadata: None,
+        };
+        let data = wrap(&payload, true).unwrap();
+        assert!(data.len() < payload.content.len() / 10);
+        assert_eq!(unwrap(&data, DEFAULT_MAX_SIZE).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_unwrap_raw_message() {
+        assert_eq!(
+            unwrap(MESSAGE, DEFAULT_MAX_SIZE).unwrap(),
+            message_payload()
+        );
+    }
+
+    #[test]
+    fn test_unwrap_exceeding_max_size() {
+        let payload = Payload {
+            content: vec![0u8; 10_000],
+            metadata: None,
+        };
+        let data = wrap(&payload, true).unwrap();
+        assert!(unwrap(&data, 9_999).is_err());
+        assert_eq!(unwrap(&data, 10_000).unwrap(), payload);
+        assert_eq!(unwrap(&data, u64::MAX).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_unwrap_unknown_flags() {
+        let mut data = wrap(&message_payload(), false).unwrap();
+        data[MAGIC.len() + 1] |= 0b1000_0000;
+        assert!(unwrap(&data, DEFAULT_MAX_SIZE).is_err());
+    }
+
+    #[test]
+    fn test_unwrap_truncated_metadata() {
+        let payload = Payload {
+            content: Vec::new(),
+            metadata: Some(FileMetadata {
+                file_name: String::from("file.bin"),
+                mime_type: String::from("application/octet-stream"),
+            }),
+        };
+        let data = wrap(&payload, false).unwrap();
+        assert!(unwrap(&data[..data.len() - 1], DEFAULT_MAX_SIZE).is_err());
+    }
+}
@@ -1,55 +1,357 @@
-use std::{fmt::Display, fs, path::Path, str::FromStr};
+//! Whole png file: signature, chunks and data stored after them.
+
+use std::{
+    collections::HashSet,
+    fmt::Display,
+    fs::File,
+    io::{self, BufReader, Read},
+    ops::Range,
+    path::Path,
+    str::FromStr,
+};
 
 use anyhow::{bail, Context, Error, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
 use reqwest::{blocking, IntoUrl};
+use serde::{de, ser::SerializeStruct, Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::{
-    chunk::{Chunk, LENGTH_FIELD_LEN},
+    chunk::{self, Chunk, ChunkRef, LENGTH_FIELD_LEN},
     chunk_type::ChunkType,
+    decoder::{self, Image},
+    error::PngError,
+    ihdr::Ihdr,
+    signature,
     source::Source,
+    stream::ChunkReader,
+    text::TextChunk,
+    validation,
 };
 
+pub(crate) const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
+pub(crate) const IHDR_CHUNK_TYPE: [u8; 4] = *b"IHDR";
+const IDAT_CHUNK_TYPE: [u8; 4] = *b"IDAT";
+pub(crate) const IEND_CHUNK_TYPE: [u8; 4] = *b"IEND";
+
+/// Problem found while parsing file in lenient mode
+#[derive(Debug, PartialEq, Eq)]
+pub enum Diagnostic {
+    /// Stored CRC doesn't match chunk content (chunk is kept with recomputed CRC)
+    CrcMismatch {
+        index: usize,
+        offset: usize,
+        chunk_type: String,
+        stored: u32,
+        computed: u32,
+    },
+    /// Last chunk is cut short, its bytes are kept as trailing data
+    TruncatedChunk { offset: usize, length: usize },
+    /// Bytes which don't form a valid chunk, they are kept as trailing data together with everything after them
+    InvalidChunk {
+        offset: usize,
+        length: usize,
+        reason: String,
+    },
+    /// Bytes after IEND chunk, kept as trailing data
+    TrailingData { offset: usize, length: usize },
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Diagnostic::CrcMismatch {
+                index,
+                offset,
+                chunk_type,
+                stored,
+                computed,
+            } => write!(
+                f,
+                "chunk #{} ({}) at byte {}: CRC mismatch (stored {:#010x}, computed {:#010x})",
+                index, chunk_type, offset, stored, computed
+            ),
+            Diagnostic::TruncatedChunk { offset, length } => {
+                write!(f, "{} bytes at byte {}: truncated chunk", length, offset)
+            }
+            Diagnostic::InvalidChunk {
+                offset,
+                length,
+                reason,
+            } => write!(f, "{} bytes at byte {}: {}", length, offset, reason),
+            Diagnostic::TrailingData { offset, length } => {
+                write!(f, "{} bytes at byte {}: data after IEND", length, offset)
+            }
+        }
+    }
+}
+
+/// Where a new chunk should be placed inside png file
+#[derive(Debug, Default, PartialEq, Eq)]
+pub enum ChunkPosition {
+    /// Right before IEND chunk (or at the end if file doesn't have one)
+    #[default]
+    BeforeIend,
+    /// Right before first chunk with given type
+    Before(ChunkType),
+    /// Right after last chunk with given type
+    After(ChunkType),
+    /// At given index in the chunk list
+    Index(usize),
+}
+
+/// Which of the chunks with given type should be used
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkSelector {
+    /// First chunk with given type
+    #[default]
+    First,
+    /// Every chunk with given type
+    All,
+    /// Chunk at given index in the chunk list (if it has given type)
+    Index(usize),
+    /// N-th chunk with given type, counting from 0
+    Nth(usize),
+}
+
+impl ChunkSelector {
+    /// Checks whether chunk at `index` in the chunk list, which is `nth` chunk of the searched type, is selected
+    pub fn matches(&self, index: usize, nth: usize) -> bool {
+        match self {
+            ChunkSelector::First => nth == 0,
+            ChunkSelector::All => true,
+            ChunkSelector::Index(selected) => *selected == index,
+            ChunkSelector::Nth(selected) => *selected == nth,
+        }
+    }
+}
+
+/// Png file with owned chunks, which can be modified and serialized back
 pub struct Png {
     signature: [u8; 8],
     chunks: Vec<Chunk>,
+    /// Bytes after IEND (or other data which doesn't form a chunk at the end of file), kept verbatim
+    trailing_data: Vec<u8>,
 }
 
 impl Png {
     const HEADER_LENGTH: usize = 8;
-    const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
+    /// Creates file with standard signature from given chunks
     pub fn from_chunks(chunks: Vec<Chunk>) -> Png {
         Png {
-            signature: Png::STANDARD_HEADER,
+            signature: STANDARD_HEADER,
             chunks,
+            trailing_data: Vec::new(),
         }
     }
 
+    /// Reads and parses file from disk, refusing invalid chunks
     pub fn from_file<P>(file_path: P) -> Result<Png>
     where
         P: AsRef<Path>,
     {
-        let file = fs::read(file_path).context("Couldn't load file.")?;
-        Png::try_from(file.as_slice()).context("Coulnd't parse png file.")
+        let file = File::open(file_path)
+            .map_err(PngError::Io)
+            .context("Couldn't load file.")?;
+        Png::from_reader(BufReader::new(file)).context("Couldn't parse png file.")
     }
 
+    /// Downloads and parses file, refusing invalid chunks
     pub fn from_url<T: IntoUrl>(url: T) -> Result<Png> {
-        let png_response = blocking::get(url)?;
-        let png_bytes: Vec<u8> = png_response.bytes()?.iter().copied().collect();
-        Png::try_from(png_bytes.as_slice())
+        let response = blocking::get(url)
+            .and_then(|response| response.error_for_status())
+            .map_err(PngError::Http)?;
+        Png::from_reader(response)
+    }
+
+    /// Reads whole png file from reader, refusing invalid chunks
+    pub fn from_reader<R: Read>(reader: R) -> Result<Png> {
+        let png = Png::read_chunks(reader)?;
+        png.ihdr()?;
+        Ok(png)
+    }
+
+    /// Reads whole png file from reader, refusing invalid chunks, but without checking that it
+    /// starts with valid IHDR, so that structure of the file can be checked separately
+    pub fn read_chunks<R: Read>(reader: R) -> Result<Png> {
+        let mut reader = ChunkReader::new(reader)?.allow_any_first_chunk();
+        let chunks = reader.by_ref().collect::<Result<Vec<Chunk>>>()?;
+        let mut png = Png::from_chunks(chunks);
+        png.trailing_data = reader.read_trailing_data()?;
+        Ok(png)
+    }
+
+    /// Parses file, recording CRC mismatches and unparsable tail data as diagnostics instead of failing.
+    /// Only invalid signature is an error.
+    pub fn parse_lenient(bytes: &[u8]) -> Result<(Png, Vec<Diagnostic>)> {
+        if !bytes.starts_with(&STANDARD_HEADER) {
+            return Err(PngError::InvalidSignature.into());
+        }
+
+        let mut chunks: Vec<Chunk> = vec![];
+        let mut diagnostics = vec![];
+        let mut offset = Png::HEADER_LENGTH;
+        while offset < bytes.len() {
+            let rest = &bytes[offset..];
+            if chunks.last().is_some_and(Png::is_iend) {
+                diagnostics.push(Diagnostic::TrailingData {
+                    offset,
+                    length: rest.len(),
+                });
+                break;
+            }
+            if rest.len() < Chunk::length_in_bytes(0) {
+                diagnostics.push(Diagnostic::TruncatedChunk {
+                    offset,
+                    length: rest.len(),
+                });
+                break;
+            }
+            let length = u32::from_be_bytes(rest[0..LENGTH_FIELD_LEN].try_into()?);
+            let chunk_type = match ChunkType::try_from(<[u8; 4]>::try_from(&rest[4..8])?) {
+                Ok(chunk_type) => chunk_type,
+                Err(_) => {
+                    diagnostics.push(Diagnostic::InvalidChunk {
+                        offset,
+                        length: rest.len(),
+                        reason: String::from("invalid chunk type"),
+                    });
+                    break;
+                }
+            };
+            if length > chunk::MAX_LENGTH {
+                diagnostics.push(Diagnostic::InvalidChunk {
+                    offset,
+                    length: rest.len(),
+                    reason: format!("chunk length {} exceeds the maximum", length),
+                });
+                break;
+            }
+            let chunk_length = Chunk::length_in_bytes(length);
+            if rest.len() < chunk_length {
+                diagnostics.push(Diagnostic::TruncatedChunk {
+                    offset,
+                    length: rest.len(),
+                });
+                break;
+            }
+
+            let data = rest[8..chunk_length - 4].to_vec();
+            let stored = u32::from_be_bytes(rest[chunk_length - 4..chunk_length].try_into()?);
+            let chunk = Chunk::new(chunk_type, data);
+            if chunk.crc() != stored {
+                diagnostics.push(Diagnostic::CrcMismatch {
+                    index: chunks.len(),
+                    offset,
+                    chunk_type: chunk.chunk_type().to_string(),
+                    stored,
+                    computed: chunk.crc(),
+                });
+            }
+            chunks.push(chunk);
+            offset += chunk_length;
+        }
+
+        let mut png = Png::from_chunks(chunks);
+        png.trailing_data = bytes[offset.min(bytes.len())..].to_vec();
+        Ok((png, diagnostics))
     }
 
+    /// Adds chunk at the end, without any placement checks
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk);
     }
 
+    /// Inserts chunk at given position, refusing placements that would make png file invalid.
+    /// Returns index of inserted chunk.
+    pub fn insert_chunk(&mut self, chunk: Chunk, position: ChunkPosition) -> Result<usize> {
+        let index = self.position_index(&position)?;
+        self.check_placement(index..index, chunk.chunk_type())?;
+        self.chunks.insert(index, chunk);
+        Ok(index)
+    }
+
+    /// Removes first chunk with given type and returns it
     pub fn remove_first_chunk(&mut self, chunk_type: &str) -> Result<Chunk> {
         let index = self
             .chunk_by_type_index(chunk_type)
-            .ok_or(Error::msg("Chunk with this type doesn't exist"))?;
+            .ok_or_else(|| PngError::MissingChunk {
+                chunk_type: chunk_type.to_string(),
+                offset: None,
+            })?;
         Ok(self.chunks.remove(index))
     }
 
+    /// Removes every chunk with given type and returns them
+    pub fn remove_all(&mut self, chunk_type: &str) -> Result<Vec<Chunk>> {
+        self.remove_chunks(chunk_type, ChunkSelector::All)
+    }
+
+    /// Removes selected chunks with given type and returns them
+    pub fn remove_chunks(
+        &mut self,
+        chunk_type: &str,
+        selector: ChunkSelector,
+    ) -> Result<Vec<Chunk>> {
+        let indices = self.select_chunks(chunk_type, selector);
+        if indices.is_empty() {
+            return Err(PngError::MissingChunk {
+                chunk_type: chunk_type.to_string(),
+                offset: None,
+            }
+            .into());
+        }
+        // Removing from the back keeps remaining indices valid
+        let mut removed: Vec<Chunk> = indices
+            .into_iter()
+            .rev()
+            .map(|index| self.chunks.remove(index))
+            .collect();
+        removed.reverse();
+        Ok(removed)
+    }
+
+    /// Replaces chunk at given index, keeping its position, and returns the old one.
+    /// Replacements that would make png file invalid are refused.
+    pub fn replace_chunk(&mut self, index: usize, chunk: Chunk) -> Result<Chunk> {
+        if index >= self.chunks.len() {
+            bail!(
+                "Index {} is out of range (file has {} chunks)",
+                index,
+                self.chunks.len()
+            );
+        }
+        let replaced = self.chunks[index].chunk_type().bytes();
+        if replaced == IHDR_CHUNK_TYPE || replaced == IEND_CHUNK_TYPE {
+            bail!(
+                "Chunk with type {} can't be replaced",
+                self.chunks[index].chunk_type()
+            );
+        }
+        self.check_placement(index..index + 1, chunk.chunk_type())?;
+        Ok(std::mem::replace(&mut self.chunks[index], chunk))
+    }
+
+    /// Returns bytes stored after the last chunk
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+
+    /// Removes bytes stored after the last chunk
+    pub fn strip_trailing_data(&mut self) {
+        self.trailing_data.clear();
+    }
+
+    /// Replaces bytes stored after the last chunk
+    pub fn set_trailing_data(&mut self, data: Vec<u8>) {
+        self.trailing_data = data;
+    }
+
+    fn is_iend(chunk: &Chunk) -> bool {
+        chunk.chunk_type().bytes() == IEND_CHUNK_TYPE
+    }
+
+    /// Png signature (first 8 bytes of file)
     pub fn header(&self) -> &[u8; 8] {
         &self.signature
     }
@@ -58,24 +360,196 @@ impl Png {
         &self.chunks
     }
 
+    /// Returns byte offset of every chunk in serialized file
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        chunk_offsets(self.chunks.iter().map(Chunk::length))
+    }
+
+    /// Returns first chunk with given type
     pub fn chunk_by_type(&self, chunk_type: &str) -> Option<&Chunk> {
         let index = self.chunk_by_type_index(chunk_type);
         index.map(|ind| &self.chunks[ind])
     }
 
     fn chunk_by_type_index(&self, chunk_type: &str) -> Option<usize> {
-        let chunk_type: Result<ChunkType> = ChunkType::from_str(chunk_type);
-        if chunk_type.is_err() {
-            return None;
+        let chunk_type = ChunkType::from_str(chunk_type).ok()?;
+        self.chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type() == &chunk_type)
+    }
+
+    /// Returns every chunk with given type
+    pub fn chunks_by_type(&self, chunk_type: &str) -> Vec<&Chunk> {
+        self.select_chunks(chunk_type, ChunkSelector::All)
+            .into_iter()
+            .map(|index| &self.chunks[index])
+            .collect()
+    }
+
+    /// Returns indices of selected chunks with given type (empty if there are none)
+    pub fn select_chunks(&self, chunk_type: &str, selector: ChunkSelector) -> Vec<usize> {
+        select_chunks(
+            self.chunks.iter().map(Chunk::chunk_type),
+            chunk_type,
+            selector,
+        )
+    }
+
+    /// Returns all valid textual chunks (tEXt, zTXt and iTXt)
+    pub fn text_chunks(&self) -> Vec<TextChunk> {
+        self.chunks
+            .iter()
+            .filter(|chunk| TextChunk::is_text_chunk_type(chunk.chunk_type()))
+            .filter_map(|chunk| TextChunk::try_from(chunk).ok())
+            .collect()
+    }
+
+    /// Returns first valid textual chunk with given keyword
+    pub fn text_by_keyword(&self, keyword: &str) -> Option<TextChunk> {
+        self.text_chunks()
+            .into_iter()
+            .find(|text| text.keyword() == keyword)
+    }
+
+    /// Sets text for keyword, replacing existing textual chunks with the same keyword
+    /// (first one is replaced in place, others are removed)
+    pub fn set_text(&mut self, text: TextChunk) -> Result<()> {
+        let chunk = text.to_chunk()?;
+        let indices = self.text_indices(text.keyword());
+        match indices.split_first() {
+            Some((first, rest)) => {
+                self.chunks[*first] = chunk;
+                for index in rest.iter().rev() {
+                    self.chunks.remove(*index);
+                }
+            }
+            None => {
+                self.insert_chunk(chunk, ChunkPosition::BeforeIend)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes all textual chunks with given keyword, returning how many were removed
+    pub fn remove_text(&mut self, keyword: &str) -> Result<usize> {
+        let indices = self.text_indices(keyword);
+        if indices.is_empty() {
+            bail!("Text with keyword {} doesn't exist", keyword);
+        }
+        for index in indices.iter().rev() {
+            self.chunks.remove(*index);
+        }
+        Ok(indices.len())
+    }
+
+    fn text_indices(&self, keyword: &str) -> Vec<usize> {
+        self.chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| TextChunk::is_text_chunk_type(chunk.chunk_type()))
+            .filter(|(_, chunk)| {
+                TextChunk::try_from(*chunk).is_ok_and(|text| text.keyword() == keyword)
+            })
+            .map(|(index, _)| index)
+            .collect()
+    }
+
+    /// Returns concatenated data of all IDAT chunks
+    pub fn image_data(&self) -> Vec<u8> {
+        self.chunks
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == IDAT_CHUNK_TYPE)
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect()
+    }
+
+    /// Returns parsed image header (first chunk)
+    pub fn ihdr(&self) -> Result<Ihdr> {
+        let chunk = self
+            .chunks
+            .first()
+            .filter(|chunk| chunk.chunk_type().bytes() == IHDR_CHUNK_TYPE)
+            .ok_or_else(missing_ihdr)?;
+        Ihdr::try_from(chunk)
+    }
+
+    /// Decodes pixels of the image
+    pub fn decode_image(&self) -> Result<Image> {
+        decoder::decode(self)
+    }
+
+    /// Replaces all IDAT chunks with new ones (each holding at most `chunk_size` bytes),
+    /// placed where the first IDAT chunk was
+    pub fn set_image_data(&mut self, data: &[u8], chunk_size: usize) -> Result<()> {
+        if chunk_size == 0 {
+            bail!("IDAT chunk size must be greater than 0");
         }
         let index = self
             .chunks
             .iter()
-            .enumerate()
-            .find(|(_, chunk)| chunk.chunk_type() == chunk_type.as_ref().unwrap());
-        index.map(|(ind, _)| ind)
+            .position(|chunk| chunk.chunk_type().bytes() == IDAT_CHUNK_TYPE)
+            .ok_or_else(|| PngError::MissingChunk {
+                chunk_type: String::from("IDAT"),
+                offset: None,
+            })?;
+        self.chunks
+            .retain(|chunk| chunk.chunk_type().bytes() != IDAT_CHUNK_TYPE);
+
+        let idat_chunks = data
+            .chunks(chunk_size)
+            .map(|bytes| Chunk::try_new(ChunkType::try_from(IDAT_CHUNK_TYPE)?, bytes.to_vec()))
+            .collect::<Result<Vec<Chunk>>>()?;
+        self.chunks.splice(index..index, idat_chunks);
+        Ok(())
+    }
+
+    fn position_index(&self, position: &ChunkPosition) -> Result<usize> {
+        match position {
+            ChunkPosition::BeforeIend => Ok(self
+                .chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type().bytes() == IEND_CHUNK_TYPE)
+                .unwrap_or(self.chunks.len())),
+            ChunkPosition::Before(chunk_type) => self
+                .chunks
+                .iter()
+                .position(|chunk| chunk.chunk_type() == chunk_type)
+                .ok_or_else(|| missing_chunk(chunk_type)),
+            ChunkPosition::After(chunk_type) => self
+                .chunks
+                .iter()
+                .rposition(|chunk| chunk.chunk_type() == chunk_type)
+                .map(|index| index + 1)
+                .ok_or_else(|| missing_chunk(chunk_type)),
+            ChunkPosition::Index(index) => {
+                if *index > self.chunks.len() {
+                    bail!(
+                        "Index {} is out of range (file has {} chunks)",
+                        index,
+                        self.chunks.len()
+                    );
+                }
+                Ok(*index)
+            }
+        }
+    }
+
+    fn check_placement(&self, range: Range<usize>, chunk_type: &ChunkType) -> Result<()> {
+        let chunk_types: Vec<ChunkType> = self
+            .chunks
+            .iter()
+            .map(|chunk| chunk.chunk_type().clone())
+            .collect();
+        let color_type = self.ihdr().ok().map(|ihdr| ihdr.color_type);
+        check_placement(
+            &chunk_types,
+            range,
+            std::slice::from_ref(chunk_type),
+            color_type,
+        )
     }
 
+    /// Serializes file (signature, chunks and trailing data)
     pub fn as_bytes(&self) -> Vec<u8> {
         let chunk_bytes: Vec<u8> = self
             .chunks()
@@ -86,6 +560,7 @@ impl Png {
             .header()
             .iter()
             .chain(chunk_bytes.iter())
+            .chain(self.trailing_data.iter())
             .copied()
             .collect();
         bytes
@@ -96,37 +571,233 @@ impl TryFrom<&[u8]> for Png {
     type Error = Error;
 
     fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
-        let mut iter = value.iter();
-        let header_buffer: Vec<u8> = iter.by_ref().take(Png::HEADER_LENGTH).copied().collect();
-        if header_buffer != Png::STANDARD_HEADER {
-            bail!("Invalid header");
+        PngRef::parse(value).map(|png| png.to_png())
+    }
+}
+
+/// Serialized as list of chunks and base64 encoded trailing data
+impl Serialize for Png {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("Png", 2)?;
+        state.serialize_field("chunks", &self.chunks)?;
+        state.serialize_field("trailing_data", &STANDARD.encode(&self.trailing_data))?;
+        state.end()
+    }
+}
+
+#[derive(Deserialize)]
+struct SerializedPng {
+    chunks: Vec<Chunk>,
+    #[serde(default, deserialize_with = "chunk::deserialize_base64")]
+    trailing_data: Vec<u8>,
+}
+
+/// Refuses files which don't start with valid IHDR chunk, like parsing from bytes does
+impl<'de> Deserialize<'de> for Png {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let serialized = SerializedPng::deserialize(deserializer)?;
+        let mut png = Png::from_chunks(serialized.chunks);
+        png.trailing_data = serialized.trailing_data;
+        png.ihdr()
+            .map_err(|error| de::Error::custom(format!("{:#}", error)))?;
+        Ok(png)
+    }
+}
+
+/// Png file whose chunks borrow their data from the parsed buffer.
+/// It's enough for reading, [`Png`] is only needed when file is modified.
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+    trailing_data: &'a [u8],
+}
+
+impl<'a> PngRef<'a> {
+    /// Parses file without copying any chunk data, refusing invalid chunks
+    pub fn parse(bytes: &'a [u8]) -> Result<PngRef<'a>> {
+        if !bytes.starts_with(&STANDARD_HEADER) {
+            return Err(PngError::InvalidSignature.into());
         }
 
-        let mut chunks: Vec<Chunk> = vec![];
+        let mut chunks: Vec<ChunkRef> = vec![];
+        let mut offset = Png::HEADER_LENGTH;
+        // Consume all chunks up to IEND, anything after it (or bytes too short to hold chunk length) is kept as is
+        while offset + LENGTH_FIELD_LEN <= bytes.len()
+            && chunks
+                .last()
+                .is_none_or(|chunk| chunk.chunk_type().bytes() != IEND_CHUNK_TYPE)
+        {
+            let chunk = ChunkRef::parse(&bytes[offset..], offset as u64)?;
+            offset += Chunk::length_in_bytes(chunk.length());
+            chunks.push(chunk);
+        }
 
-        // Consume all chunks
-        loop {
-            let length_buffer: Vec<u8> = iter.by_ref().take(LENGTH_FIELD_LEN).copied().collect();
-            if length_buffer.len() < LENGTH_FIELD_LEN {
-                break;
-            }
-            let length = u32::from_be_bytes(length_buffer[0..LENGTH_FIELD_LEN].try_into()?);
-            let chunk_length = Chunk::length_in_bytes(length);
-            let remaining_chunk_data: Vec<u8> = iter
-                .by_ref()
-                .take(chunk_length - LENGTH_FIELD_LEN)
-                .copied()
-                .collect();
-            let chunk_bytes: Vec<u8> = length_buffer
-                .iter()
-                .chain(remaining_chunk_data.iter())
-                .copied()
-                .collect();
-            chunks.push(Chunk::try_from(chunk_bytes.as_slice())?);
+        let first = chunks
+            .first()
+            .filter(|chunk| chunk.chunk_type().bytes() == IHDR_CHUNK_TYPE)
+            .ok_or_else(missing_ihdr)?;
+        Ihdr::try_from(&first.to_chunk())?;
+        Ok(PngRef {
+            chunks,
+            trailing_data: &bytes[offset..],
+        })
+    }
+
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks
+    }
+
+    /// Returns byte offset of every chunk in file
+    pub fn chunk_offsets(&self) -> Vec<usize> {
+        chunk_offsets(self.chunks.iter().map(ChunkRef::length))
+    }
+
+    /// Returns indices of selected chunks with given type (empty if there are none)
+    pub fn select_chunks(&self, chunk_type: &str, selector: ChunkSelector) -> Vec<usize> {
+        select_chunks(
+            self.chunks.iter().map(ChunkRef::chunk_type),
+            chunk_type,
+            selector,
+        )
+    }
+
+    /// Returns bytes stored after the last chunk
+    pub fn trailing_data(&self) -> &'a [u8] {
+        self.trailing_data
+    }
+
+    /// Copies all chunks, so that file can be modified
+    pub fn to_png(&self) -> Png {
+        let mut png = Png::from_chunks(self.chunks.iter().map(ChunkRef::to_chunk).collect());
+        png.trailing_data = self.trailing_data.to_vec();
+        png
+    }
+}
+
+impl Display for PngRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write_message_chunk_types(f, self.chunks.iter().map(ChunkRef::chunk_type))
+    }
+}
+
+fn select_chunks<'a>(
+    chunk_types: impl Iterator<Item = &'a ChunkType>,
+    chunk_type: &str,
+    selector: ChunkSelector,
+) -> Vec<usize> {
+    let Ok(chunk_type) = ChunkType::from_str(chunk_type) else {
+        return Vec::new();
+    };
+    chunk_types
+        .enumerate()
+        .filter(|(_, other)| *other == &chunk_type)
+        .enumerate()
+        .filter(|(nth, (index, _))| selector.matches(*index, *nth))
+        .map(|(_, (index, _))| index)
+        .collect()
+}
+
+/// Offsets of chunks with data of given lengths, placed right after png signature
+fn chunk_offsets(lengths: impl Iterator<Item = u32>) -> Vec<usize> {
+    lengths
+        .scan(Png::HEADER_LENGTH, |offset, length| {
+            let chunk_offset = *offset;
+            *offset += Chunk::length_in_bytes(length);
+            Some(chunk_offset)
+        })
+        .collect()
+}
+
+pub(crate) fn missing_chunk(chunk_type: &ChunkType) -> Error {
+    PngError::MissingChunk {
+        chunk_type: chunk_type.to_string(),
+        offset: None,
+    }
+    .into()
+}
+
+/// Error of file whose first chunk (right after signature) isn't IHDR
+pub(crate) fn missing_ihdr() -> Error {
+    PngError::MissingChunk {
+        chunk_type: String::from("IHDR"),
+        offset: Some(Png::HEADER_LENGTH as u64),
+    }
+    .into()
+}
+
+/// Checks whether chunks of `chunk_types` in given range (empty for plain insertion) can be replaced
+/// with chunks of `inserted` types, refusing placements that would make png file invalid:
+/// ones breaking any ordering rule which the file didn't break before, or separating chunk from its signature.
+/// `color_type` comes from IHDR (if it's known).
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Summary-of-standard-chunks
+pub fn check_placement(
+    chunk_types: &[ChunkType],
+    range: Range<usize>,
+    inserted: &[ChunkType],
+    color_type: Option<u8>,
+) -> Result<()> {
+    let is_type = |other: Option<&ChunkType>, bytes: [u8; 4]| {
+        other.is_some_and(|other| other.bytes() == bytes)
+    };
+    let previous = range.start.checked_sub(1).map(|index| &chunk_types[index]);
+    let next = chunk_types.get(range.end);
+
+    for chunk_type in inserted {
+        if chunk_type.bytes() == IHDR_CHUNK_TYPE || chunk_type.bytes() == IEND_CHUNK_TYPE {
+            bail!("Chunk with type {} can't be inserted", chunk_type);
         }
+    }
+    if is_type(next, IHDR_CHUNK_TYPE) {
+        bail!("Chunk can't be placed before IHDR chunk");
+    }
+    if is_type(previous, IEND_CHUNK_TYPE) {
+        bail!("Chunk can't be placed after IEND chunk");
+    }
+    if inserted
+        .iter()
+        .any(|chunk_type| chunk_type.bytes() != IDAT_CHUNK_TYPE)
+        && is_type(previous, IDAT_CHUNK_TYPE)
+        && is_type(next, IDAT_CHUNK_TYPE)
+    {
+        bail!("Chunk can't be placed between consecutive IDAT chunks");
+    }
+    let is_signature = |other: Option<&ChunkType>| {
+        other.is_some_and(|other| other.to_string() == signature::SIGNATURE_CHUNK_TYPE)
+    };
+    if let Some(previous) = previous {
+        if range.is_empty() && is_signature(next) && !is_signature(Some(previous)) {
+            bail!(
+                "Chunk can't be placed between {} chunk and its signature",
+                previous
+            );
+        }
+    }
 
-        Ok(Png::from_chunks(chunks))
+    let known: HashSet<(Option<usize>, String)> =
+        validation::ordering_violations(chunk_types, color_type)
+            .into_iter()
+            .collect();
+    let candidate: Vec<ChunkType> = chunk_types[..range.start]
+        .iter()
+        .chain(inserted)
+        .chain(&chunk_types[range.end..])
+        .cloned()
+        .collect();
+    // Index of chunk before the change (replacing chunk takes place of the replaced one)
+    let original_index = |index: usize| match index.checked_sub(range.start) {
+        None => Some(index),
+        Some(offset) if offset < inserted.len() => (offset < range.len()).then_some(index),
+        Some(_) => Some(index - inserted.len() + range.len()),
+    };
+    for (index, message) in validation::ordering_violations(&candidate, color_type) {
+        let original = match index {
+            Some(index) => original_index(index).map(Some),
+            None => Some(None),
+        };
+        if !original.is_some_and(|original| known.contains(&(original, message.clone()))) {
+            bail!("Chunk placement would make png file invalid: {}", message);
+        }
     }
+    Ok(())
 }
 
 impl TryFrom<&Source> for Png {
@@ -136,19 +807,27 @@ impl TryFrom<&Source> for Png {
         match value {
             Source::Path(path) => Png::from_file(path),
             Source::Url(url) => Png::from_url(url.clone()),
+            Source::Stdin => Png::from_reader(io::stdin()),
         }
     }
 }
 
 impl Display for Png {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        for chunk in self.chunks() {
-            if chunk.chunk_type().is_potential_message() {
-                writeln!(f, "{}", chunk.chunk_type())?;
-            }
+        write_message_chunk_types(f, self.chunks.iter().map(Chunk::chunk_type))
+    }
+}
+
+fn write_message_chunk_types<'a>(
+    f: &mut std::fmt::Formatter<'_>,
+    chunk_types: impl Iterator<Item = &'a ChunkType>,
+) -> std::fmt::Result {
+    for chunk_type in chunk_types {
+        if chunk_type.is_potential_message() {
+            writeln!(f, "{}", chunk_type)?;
         }
-        Ok(())
     }
+    Ok(())
 }
 
 #[cfg(test)]
@@ -166,6 +845,13 @@ mod tests {
         ]
     }
 
+    fn testing_chunks_with_ihdr() -> Vec<Chunk> {
+        let mut chunks = testing_chunks();
+        let ihdr = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+        chunks.insert(0, Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr));
+        chunks
+    }
+
     fn testing_png() -> Png {
         let chunks = testing_chunks();
         Png::from_chunks(chunks)
@@ -190,22 +876,241 @@ mod tests {
 
     #[test]
     fn test_valid_from_bytes() {
-        let chunk_bytes: Vec<u8> = testing_chunks()
+        let chunk_bytes: Vec<u8> = testing_chunks_with_ihdr()
             .into_iter()
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
-        let bytes: Vec<u8> = Png::STANDARD_HEADER
+        let bytes: Vec<u8> = STANDARD_HEADER
             .iter()
             .chain(chunk_bytes.iter())
             .copied()
             .collect();
 
-        let png = Png::try_from(bytes.as_ref());
+        let png = Png::try_from(bytes.as_slice());
 
         assert!(png.is_ok());
     }
 
+    #[test]
+    fn test_from_bytes_without_ihdr() {
+        let chunk_bytes: Vec<u8> = testing_chunks()
+            .into_iter()
+            .flat_map(|chunk| chunk.as_bytes())
+            .collect();
+        let bytes: Vec<u8> = STANDARD_HEADER
+            .iter()
+            .chain(chunk_bytes.iter())
+            .copied()
+            .collect();
+
+        let error = Png::try_from(bytes.as_slice()).err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<PngError>(),
+            Some(PngError::MissingChunk { chunk_type, offset: Some(8) }) if chunk_type == "IHDR"
+        ));
+    }
+
+    #[test]
+    fn test_from_bytes_with_invalid_ihdr() {
+        let mut chunks = testing_chunks();
+        // 16-bit indexed-colour image
+        let data = vec![0, 0, 0, 1, 0, 0, 0, 1, 16, 3, 0, 0, 0];
+        chunks.insert(0, Chunk::new(ChunkType::from_str("IHDR").unwrap(), data));
+        let bytes: Vec<u8> = STANDARD_HEADER
+            .iter()
+            .copied()
+            .chain(chunks.iter().flat_map(|chunk| chunk.as_bytes()))
+            .collect();
+
+        let error = Png::try_from(bytes.as_slice()).err().unwrap();
+        assert!(error.to_string().contains("bit depth"));
+    }
+
+    #[test]
+    fn test_parse_lenient_valid_file() {
+        let (png, diagnostics) = Png::parse_lenient(&PNG_FILE).unwrap();
+        assert!(diagnostics.is_empty());
+        assert_eq!(png.as_bytes(), PNG_FILE);
+    }
+
+    #[test]
+    fn test_parse_lenient_crc_mismatch() {
+        let mut bytes = PNG_FILE.to_vec();
+        // Last byte of IHDR CRC
+        bytes[32] ^= 0xFF;
+        let (png, diagnostics) = Png::parse_lenient(&bytes).unwrap();
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::CrcMismatch {
+                index: 0,
+                offset: 8,
+                chunk_type: String::from("IHDR"),
+                stored: u32::from_be_bytes([bytes[29], bytes[30], bytes[31], bytes[32]]),
+                computed: u32::from_be_bytes([
+                    PNG_FILE[29],
+                    PNG_FILE[30],
+                    PNG_FILE[31],
+                    PNG_FILE[32]
+                ]),
+            }]
+        );
+        assert_eq!(png.as_bytes(), PNG_FILE);
+        assert!(Png::try_from(bytes.as_slice()).is_err());
+    }
+
+    #[test]
+    fn test_parse_lenient_broken_tail() {
+        let (png, diagnostics) = Png::parse_lenient(&PNG_FILE[..PNG_FILE.len() - 5]).unwrap();
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::TruncatedChunk {
+                offset: PNG_FILE.len() - 12,
+                length: 7
+            }]
+        );
+        assert_eq!(png.chunks().len(), testing_png_file_chunk_count() - 1);
+        assert_eq!(png.trailing_data().len(), 7);
+
+        let bytes: Vec<u8> = PNG_FILE.iter().chain(b"junk").copied().collect();
+        let (mut png, diagnostics) = Png::parse_lenient(&bytes).unwrap();
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::TrailingData {
+                offset: PNG_FILE.len(),
+                length: 4
+            }]
+        );
+        assert_eq!(png.as_bytes(), bytes);
+        png.strip_trailing_data();
+        assert_eq!(png.as_bytes(), PNG_FILE);
+
+        let mut bytes = PNG_FILE.to_vec();
+        // First letter of IEND chunk type
+        let iend_type_offset = PNG_FILE.len() - 8;
+        bytes[iend_type_offset] = b'1';
+        let (_, diagnostics) = Png::parse_lenient(&bytes).unwrap();
+        assert_eq!(
+            diagnostics,
+            [Diagnostic::InvalidChunk {
+                offset: PNG_FILE.len() - 12,
+                length: 12,
+                reason: String::from("invalid chunk type")
+            }]
+        );
+    }
+
+    #[test]
+    fn test_round_trip_is_byte_exact() {
+        let with_suffix =
+            |suffix: &[u8]| -> Vec<u8> { PNG_FILE.iter().chain(suffix).copied().collect() };
+        let inputs = [
+            PNG_FILE.to_vec(),
+            with_suffix(b"junk appended by other tool"),
+            // Too short to hold chunk length
+            with_suffix(b"ab"),
+            // Polyglot: another png (or zip, pdf...) glued after IEND
+            with_suffix(&PNG_FILE),
+            with_suffix(&[0; 1000]),
+        ];
+        for input in inputs {
+            let png = Png::try_from(input.as_slice()).unwrap();
+            assert_eq!(png.chunks().len(), testing_png_file_chunk_count());
+            assert_eq!(png.trailing_data(), &input[PNG_FILE.len()..]);
+            assert_eq!(png.as_bytes(), input);
+        }
+    }
+
+    #[test]
+    fn test_png_serde() {
+        let bytes: Vec<u8> = PNG_FILE.iter().chain(b"trailer").copied().collect();
+        let png = Png::try_from(bytes.as_slice()).unwrap();
+
+        let yaml = serde_norway::to_string(&png).unwrap();
+        let parsed: Png = serde_norway::from_str(&yaml).unwrap();
+        assert_eq!(parsed.as_bytes(), bytes);
+
+        let json = serde_json::to_value(&png).unwrap();
+        assert_eq!(json["chunks"][0]["chunk_type"], "IHDR");
+        assert_eq!(json["trailing_data"], "dHJhaWxlcg==");
+
+        let without_ihdr = r#"{"chunks": [{"chunk_type": "IEND", "data": ""}]}"#;
+        let error = serde_json::from_str::<Png>(without_ihdr).err().unwrap();
+        assert!(error
+            .to_string()
+            .starts_with("Chunk with type IHDR is missing at byte 8"));
+        let mut invalid_ihdr = json.clone();
+        invalid_ihdr["chunks"][0]["data"] = "AAAAAQAAAAEDAgAAAA==".into();
+        invalid_ihdr["chunks"][0]["crc"] = serde_json::Value::Null;
+        assert!(serde_json::from_value::<Png>(invalid_ihdr).is_err());
+    }
+
+    #[test]
+    fn test_trailing_data_survives_editing() {
+        let bytes: Vec<u8> = PNG_FILE.iter().chain(b"trailer").copied().collect();
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "message").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        assert!(png.as_bytes().ends_with(b"IEND\xaeB`\x82trailer"));
+
+        png.strip_trailing_data();
+        assert!(png.trailing_data().is_empty());
+        assert!(png.as_bytes().ends_with(b"IEND\xaeB`\x82"));
+    }
+
+    #[test]
+    fn test_png_ref_borrows_data() {
+        let bytes: Vec<u8> = PNG_FILE.iter().chain(b"trailer").copied().collect();
+        let png = PngRef::parse(&bytes).unwrap();
+        assert_eq!(png.chunks().len(), testing_png_file_chunk_count());
+        assert!(png
+            .chunks()
+            .iter()
+            .all(|chunk| bytes.as_ptr_range().contains(&chunk.data().as_ptr())));
+        assert_eq!(png.trailing_data(), b"trailer");
+        assert_eq!(png.to_png().as_bytes(), bytes);
+        assert_eq!(
+            Png::from_reader(bytes.as_slice()).unwrap().as_bytes(),
+            bytes
+        );
+    }
+
+    #[test]
+    fn test_parse_lenient_invalid_header() {
+        assert!(Png::parse_lenient(&PNG_FILE[1..]).is_err());
+    }
+
+    fn testing_png_file_chunk_count() -> usize {
+        Png::try_from(&PNG_FILE[..]).unwrap().chunks().len()
+    }
+
+    #[test]
+    fn test_chunk_offsets() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let offsets = png.chunk_offsets();
+        assert_eq!(offsets.len(), png.chunks().len());
+        for (offset, chunk) in offsets.iter().zip(png.chunks()) {
+            assert_eq!(
+                &PNG_FILE[*offset + 4..*offset + 8],
+                chunk.chunk_type().bytes()
+            );
+        }
+    }
+
+    #[test]
+    fn test_ihdr() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let ihdr = png.ihdr().unwrap();
+        assert_eq!((ihdr.width, ihdr.height), (50, 50));
+        assert_eq!((ihdr.bit_depth, ihdr.color_type), (8, 6));
+        assert_eq!(ihdr.interlace_method, 0);
+
+        assert!(testing_png().ihdr().is_err());
+    }
+
     #[test]
     fn test_invalid_header() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -219,7 +1124,7 @@ mod tests {
             .copied()
             .collect();
 
-        let png = Png::try_from(bytes.as_ref());
+        let png = Png::try_from(bytes.as_slice());
 
         assert!(png.is_err());
     }
@@ -241,7 +1146,7 @@ mod tests {
 
         chunk_bytes.append(&mut bad_chunk);
 
-        let png = Png::try_from(chunk_bytes.as_ref());
+        let png = Png::try_from(chunk_bytes.as_slice());
 
         assert!(png.is_err());
     }
@@ -279,6 +1184,358 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    fn testing_png_with_messages() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("ruSt", "first").unwrap(),
+            chunk_from_strings("teSt", "first").unwrap(),
+            chunk_from_strings("IDAT", "first data").unwrap(),
+            chunk_from_strings("IDAT", "second data").unwrap(),
+            chunk_from_strings("ruSt", "second").unwrap(),
+            chunk_from_strings("teSt", "second").unwrap(),
+            chunk_from_strings("ruSt", "third").unwrap(),
+            chunk_from_strings("teSt", "third").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    #[test]
+    fn test_select_chunks() {
+        let png = testing_png_with_messages();
+        assert_eq!(png.select_chunks("ruSt", ChunkSelector::First), [1]);
+        assert_eq!(png.select_chunks("ruSt", ChunkSelector::All), [1, 5, 7]);
+        assert_eq!(png.select_chunks("ruSt", ChunkSelector::Nth(1)), [5]);
+        assert_eq!(png.select_chunks("ruSt", ChunkSelector::Index(7)), [7]);
+        assert!(png
+            .select_chunks("ruSt", ChunkSelector::Index(2))
+            .is_empty());
+        assert!(png.select_chunks("ruSt", ChunkSelector::Nth(3)).is_empty());
+        assert!(png.select_chunks("ru1t", ChunkSelector::All).is_empty());
+
+        let messages: Vec<String> = png
+            .chunks_by_type("ruSt")
+            .iter()
+            .map(|chunk| chunk.data_as_string().unwrap())
+            .collect();
+        assert_eq!(messages, ["first", "second", "third"]);
+    }
+
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png_with_messages();
+        let removed = png.remove_chunks("teSt", ChunkSelector::Nth(1)).unwrap();
+        assert_eq!(removed[0].data_as_string().unwrap(), "second");
+
+        let removed = png.remove_all("ruSt").unwrap();
+        assert_eq!(removed.len(), 3);
+        assert_eq!(removed[2].data_as_string().unwrap(), "third");
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "teSt", "IDAT", "IDAT", "teSt", "IEND"]
+        );
+
+        let error = png.remove_all("ruSt").err().unwrap();
+        assert!(matches!(
+            error.downcast_ref::<PngError>(),
+            Some(PngError::MissingChunk { .. })
+        ));
+    }
+
+    #[test]
+    fn test_replace_chunk() {
+        let mut png = testing_png_with_messages();
+        let old = png
+            .replace_chunk(5, chunk_from_strings("ruSt", "updated").unwrap())
+            .unwrap();
+        assert_eq!(old.data_as_string().unwrap(), "second");
+        assert_eq!(png.chunks()[5].data_as_string().unwrap(), "updated");
+        assert_eq!(png.chunks().len(), 10);
+
+        let rust = || chunk_from_strings("ruSt", "x").unwrap();
+        assert!(png.replace_chunk(0, rust()).is_err());
+        assert!(png.replace_chunk(9, rust()).is_err());
+        assert!(png.replace_chunk(10, rust()).is_err());
+    }
+
+    fn testing_image_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "first data").unwrap(),
+            chunk_from_strings("IDAT", "second data").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn chunk_types(png: &Png) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_chunk_before_iend() {
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "Message").unwrap(),
+            ChunkPosition::default(),
+        )
+        .unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "IDAT", "ruSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_without_iend() {
+        let mut png = testing_png();
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "Message").unwrap(),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+        assert_eq!(chunk_types(&png), ["FrSt", "miDl", "LASt", "ruSt"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_before_and_after() {
+        use std::str::FromStr;
+
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "Before").unwrap(),
+            ChunkPosition::Before(ChunkType::from_str("IDAT").unwrap()),
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "After").unwrap(),
+            ChunkPosition::After(ChunkType::from_str("IDAT").unwrap()),
+        )
+        .unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "ruSt", "IDAT", "IDAT", "ruSt", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_insert_chunk_at_index() {
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "Message").unwrap(),
+            ChunkPosition::Index(1),
+        )
+        .unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "ruSt", "IDAT", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_invalid_placement() {
+        use std::str::FromStr;
+
+        let mut png = testing_image_png();
+        let positions = [
+            ChunkPosition::Index(0),
+            ChunkPosition::Index(2),
+            ChunkPosition::Index(4),
+            ChunkPosition::Index(5),
+            ChunkPosition::After(ChunkType::from_str("IEND").unwrap()),
+            ChunkPosition::Before(ChunkType::from_str("ruSt").unwrap()),
+        ];
+        for position in positions {
+            let chunk = chunk_from_strings("ruSt", "Message").unwrap();
+            assert!(png.insert_chunk(chunk, position).is_err());
+        }
+        assert_eq!(png.chunks().len(), 4);
+    }
+
+    #[test]
+    fn test_insert_chunk_ordering_rules() {
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("PLTE", "palette").unwrap(),
+            ChunkPosition::Index(1),
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("tIME", "time").unwrap(),
+            ChunkPosition::default(),
+        )
+        .unwrap();
+        let refused = [
+            (
+                "PLTE",
+                ChunkPosition::default(),
+                "PLTE: chunk can appear only once",
+            ),
+            (
+                "gAMA",
+                ChunkPosition::Index(2),
+                "gAMA: must appear before PLTE",
+            ),
+            (
+                "sRGB",
+                ChunkPosition::default(),
+                "sRGB: must appear before PLTE",
+            ),
+            (
+                "tRNS",
+                ChunkPosition::Index(1),
+                "tRNS: must appear after PLTE",
+            ),
+            (
+                "bKGD",
+                ChunkPosition::default(),
+                "bKGD: must appear before IDAT",
+            ),
+            (
+                "pHYs",
+                ChunkPosition::default(),
+                "pHYs: must appear before IDAT",
+            ),
+            (
+                "tIME",
+                ChunkPosition::Index(1),
+                "tIME: chunk can appear only once",
+            ),
+        ];
+        for (chunk_type, position, message) in refused {
+            let chunk = chunk_from_strings(chunk_type, "data").unwrap();
+            assert_eq!(
+                png.insert_chunk(chunk, position).unwrap_err().to_string(),
+                format!("Chunk placement would make png file invalid: {}", message),
+            );
+        }
+        for (chunk_type, index) in [("gAMA", 1), ("tRNS", 3), ("sPLT", 4), ("sPLT", 5)] {
+            let chunk = chunk_from_strings(chunk_type, "data").unwrap();
+            png.insert_chunk(chunk, ChunkPosition::Index(index))
+                .unwrap();
+        }
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "gAMA", "PLTE", "tRNS", "sPLT", "sPLT", "IDAT", "IDAT", "tIME", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_insert_chunk_keeps_existing_violations() {
+        // Already misplaced chunks don't prevent other changes
+        let mut png = Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("IDAT", "data").unwrap(),
+            chunk_from_strings("gAMA", "late").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ]);
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "Message").unwrap(),
+            ChunkPosition::default(),
+        )
+        .unwrap();
+        png.replace_chunk(2, chunk_from_strings("gAMA", "updated").unwrap())
+            .unwrap();
+        assert!(png
+            .replace_chunk(3, chunk_from_strings("sRGB", "moved").unwrap())
+            .is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_next_to_signature() {
+        let mut png = testing_image_png();
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "Message").unwrap(),
+            ChunkPosition::default(),
+        )
+        .unwrap();
+        png.insert_chunk(
+            chunk_from_strings("siGn", "signature").unwrap(),
+            ChunkPosition::default(),
+        )
+        .unwrap();
+        let error = png
+            .insert_chunk(
+                chunk_from_strings("ruSt", "Other").unwrap(),
+                ChunkPosition::Index(4),
+            )
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Chunk can't be placed between ruSt chunk and its signature"
+        );
+        png.insert_chunk(
+            chunk_from_strings("ruSt", "Other").unwrap(),
+            ChunkPosition::Index(5),
+        )
+        .unwrap();
+    }
+
+    fn text(keyword: &str, text: &str) -> TextChunk {
+        TextChunk::Text {
+            keyword: keyword.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_set_and_get_text() {
+        let mut png = testing_image_png();
+        png.set_text(text("Author", "Someone")).unwrap();
+        png.set_text(text("Title", "Dice")).unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "IDAT", "IDAT", "tEXt", "tEXt", "IEND"]
+        );
+        assert_eq!(png.text_chunks().len(), 2);
+        assert_eq!(png.text_by_keyword("Title").unwrap().text(), "Dice");
+        assert!(png.text_by_keyword("Comment").is_none());
+    }
+
+    #[test]
+    fn test_set_text_replaces_existing() {
+        let mut png = testing_image_png();
+        png.set_text(text("Author", "Someone")).unwrap();
+        png.append_chunk(text("Author", "Duplicate").to_chunk().unwrap());
+        png.set_text(TextChunk::Compressed {
+            keyword: String::from("Author"),
+            text: String::from("Someone else"),
+        })
+        .unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "IDAT", "zTXt", "IEND"]);
+        assert_eq!(
+            png.text_by_keyword("Author").unwrap().text(),
+            "Someone else"
+        );
+    }
+
+    #[test]
+    fn test_remove_text() {
+        let mut png = testing_image_png();
+        png.set_text(text("Author", "Someone")).unwrap();
+        png.set_text(text("Title", "Dice")).unwrap();
+        assert_eq!(png.remove_text("Author").unwrap(), 1);
+        assert!(png.text_by_keyword("Author").is_none());
+        assert!(png.text_by_keyword("Title").is_some());
+        assert!(png.remove_text("Author").is_err());
+    }
+
+    #[test]
+    fn test_image_data() {
+        let png = testing_image_png();
+        assert_eq!(png.image_data(), b"first datasecond data");
+    }
+
+    #[test]
+    fn test_set_image_data() {
+        let mut png = testing_image_png();
+        png.set_text(text("Title", "Dice")).unwrap();
+        png.set_image_data(b"new image data", 5).unwrap();
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "IDAT", "IDAT", "IDAT", "tEXt", "IEND"]
+        );
+        assert_eq!(png.image_data(), b"new image data");
+
+        let mut png = testing_png();
+        assert!(png.set_image_data(b"new image data", 5).is_err());
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -295,18 +1552,18 @@ mod tests {
 
     #[test]
     fn test_png_trait_impls() {
-        let chunk_bytes: Vec<u8> = testing_chunks()
+        let chunk_bytes: Vec<u8> = testing_chunks_with_ihdr()
             .into_iter()
             .flat_map(|chunk| chunk.as_bytes())
             .collect();
 
-        let bytes: Vec<u8> = Png::STANDARD_HEADER
+        let bytes: Vec<u8> = STANDARD_HEADER
             .iter()
             .chain(chunk_bytes.iter())
             .copied()
             .collect();
 
-        let png: Png = TryFrom::try_from(bytes.as_ref()).unwrap();
+        let png: Png = TryFrom::try_from(bytes.as_slice()).unwrap();
 
         let _png_string = format!("{}", png);
     }
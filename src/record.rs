@@ -0,0 +1,191 @@
+//! Flat descriptions of chunks, used for machine-readable output.
+
+use std::str;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use serde::Serialize;
+
+use crate::{chunk::ChunkRef, chunk_type::ChunkType, png::PngRef, registry};
+
+/// How bytes are written as text
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub enum Encoding {
+    #[serde(rename = "utf-8")]
+    Utf8,
+    #[serde(rename = "base64")]
+    Base64,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Utf8 => "utf-8",
+            Encoding::Base64 => "base64",
+        }
+    }
+}
+
+/// Writes bytes as they are if they are valid utf-8, otherwise encodes them with base64
+pub fn encode_payload(data: &[u8]) -> (Encoding, String) {
+    match str::from_utf8(data) {
+        Ok(text) => (Encoding::Utf8, text.to_string()),
+        Err(_) => (Encoding::Base64, STANDARD.encode(data)),
+    }
+}
+
+/// Chunk together with its position in file and properties encoded in its type
+#[derive(Debug, Serialize)]
+pub struct ChunkRecord {
+    /// Index in the chunk list
+    pub index: usize,
+    /// Byte offset of the chunk in file
+    pub offset: u64,
+    pub chunk_type: ChunkType,
+    pub critical: bool,
+    pub public: bool,
+    pub reserved_bit_valid: bool,
+    pub safe_to_copy: bool,
+    /// Length of chunk data in bytes
+    pub length: u32,
+    pub crc: u32,
+    /// Description of known chunk type
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<&'static str>,
+    /// Decoded content of standard chunk
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// How `payload` is written
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<Encoding>,
+    /// Chunk data
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+}
+
+impl ChunkRecord {
+    /// Describes chunk, without its data
+    pub fn new(index: usize, offset: u64, chunk: &ChunkRef) -> ChunkRecord {
+        let chunk_type = chunk.chunk_type();
+        ChunkRecord {
+            index,
+            offset,
+            chunk_type: chunk_type.clone(),
+            critical: chunk_type.is_critical(),
+            public: chunk_type.is_public(),
+            reserved_bit_valid: chunk_type.is_reserved_bit_valid(),
+            safe_to_copy: chunk_type.is_safe_to_copy(),
+            length: chunk.length(),
+            crc: chunk.crc(),
+            description: registry::describe(chunk_type),
+            summary: registry::summarize(chunk_type, chunk.data()),
+            encoding: None,
+            payload: None,
+        }
+    }
+
+    /// Describes chunk together with its data
+    pub fn with_payload(index: usize, offset: u64, chunk: &ChunkRef) -> ChunkRecord {
+        let (encoding, payload) = encode_payload(chunk.data());
+        ChunkRecord {
+            encoding: Some(encoding),
+            payload: Some(payload),
+            ..ChunkRecord::new(index, offset, chunk)
+        }
+    }
+
+    /// Names of properties encoded in chunk type
+    pub fn flags(&self) -> Vec<&'static str> {
+        let mut flags = vec![
+            if self.critical {
+                "critical"
+            } else {
+                "ancillary"
+            },
+            if self.public { "public" } else { "private" },
+            if self.safe_to_copy {
+                "safe-to-copy"
+            } else {
+                "unsafe-to-copy"
+            },
+        ];
+        if !self.reserved_bit_valid {
+            flags.push("reserved-bit-set");
+        }
+        flags
+    }
+}
+
+/// Describes every chunk of the file, including data of chunks matching `with_payload`
+pub fn chunk_records<F>(png: &PngRef, with_payload: F) -> Vec<ChunkRecord>
+where
+    F: Fn(&ChunkRef) -> bool,
+{
+    png.chunks()
+        .iter()
+        .zip(png.chunk_offsets())
+        .enumerate()
+        .map(|(index, (chunk, offset))| {
+            if with_payload(chunk) {
+                ChunkRecord::with_payload(index, offset as u64, chunk)
+            } else {
+                ChunkRecord::new(index, offset as u64, chunk)
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        chunk::Chunk,
+        png::{ChunkPosition, Png},
+    };
+    use std::str::FromStr;
+
+    #[test]
+    fn test_encode_payload() {
+        assert_eq!(
+            encode_payload(b"hello"),
+            (Encoding::Utf8, String::from("hello"))
+        );
+        assert_eq!(
+            encode_payload(&[0, 159, 146, 150]),
+            (Encoding::Base64, String::from("AJ+Slg=="))
+        );
+    }
+
+    #[test]
+    fn test_chunk_records() {
+        let ihdr = vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0];
+        let mut png = Png::from_chunks(vec![
+            Chunk::new(ChunkType::from_str("IHDR").unwrap(), ihdr),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), Vec::new()),
+        ]);
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"hello".to_vec());
+        png.insert_chunk(chunk, ChunkPosition::BeforeIend).unwrap();
+        let bytes = png.as_bytes();
+        let png = PngRef::parse(&bytes).unwrap();
+
+        let records = chunk_records(&png, |chunk| chunk.chunk_type().is_potential_message());
+        assert_eq!(records.len(), 3);
+        let record = &records[1];
+        assert_eq!(record.index, 1);
+        assert_eq!(record.offset, 33);
+        assert_eq!(record.flags(), ["ancillary", "private", "safe-to-copy"]);
+        assert_eq!(record.length, 5);
+        assert_eq!(record.payload.as_deref(), Some("hello"));
+        assert_eq!(records[0].payload, None);
+        assert_eq!(records[0].description, Some("Image header"));
+        assert_eq!(
+            records[0].summary.as_deref(),
+            Some("1x1, 8-bit truecolor, non-interlaced")
+        );
+
+        let json = serde_json::to_value(record).unwrap();
+        assert_eq!(json["chunk_type"], "ruSt");
+        assert_eq!(json["encoding"], "utf-8");
+        assert_eq!(json["crc"], png.chunks()[1].crc());
+        assert!(json.get("description").is_none());
+    }
+}
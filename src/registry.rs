@@ -0,0 +1,248 @@
+//! Descriptions of known chunk types and summaries of standard chunks.
+
+use anyhow::{bail, Result};
+
+use crate::{
+    chunk::Chunk,
+    chunk_type::ChunkType,
+    ihdr::{self, Ihdr},
+    signature, text,
+};
+
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html
+// https://www.w3.org/TR/png-3/#4Concepts.FormatTypes
+const KNOWN_CHUNK_TYPES: [(&str, &str); 35] = [
+    ("IHDR", "Image header"),
+    ("PLTE", "Palette"),
+    ("IDAT", "Image data"),
+    ("IEND", "Image trailer"),
+    ("cHRM", "Primary chromaticities and white point"),
+    ("gAMA", "Image gamma"),
+    ("iCCP", "Embedded ICC profile"),
+    ("sBIT", "Significant bits"),
+    ("sRGB", "Standard RGB colour space"),
+    ("cICP", "Coding-independent code points"),
+    ("mDCV", "Mastering display colour volume"),
+    ("cLLI", "Content light level information"),
+    ("tEXt", "Textual data"),
+    ("zTXt", "Compressed textual data"),
+    ("iTXt", "International textual data"),
+    ("bKGD", "Background colour"),
+    ("hIST", "Image histogram"),
+    ("tRNS", "Transparency"),
+    ("pHYs", "Physical pixel dimensions"),
+    ("sPLT", "Suggested palette"),
+    ("eXIf", "Exif metadata"),
+    ("tIME", "Image last-modification time"),
+    ("acTL", "Animation control (APNG)"),
+    ("fcTL", "Frame control (APNG)"),
+    ("fdAT", "Frame data (APNG)"),
+    ("oFFs", "Image offset"),
+    ("pCAL", "Calibration of pixel values"),
+    ("sCAL", "Physical scale of image subject"),
+    ("sTER", "Indicator of stereo image"),
+    ("gIFg", "GIF graphic control extension"),
+    ("gIFt", "GIF plain text extension"),
+    ("gIFx", "GIF application extension"),
+    ("dSIG", "Digital signature"),
+    ("CgBI", "Apple optimized png marker"),
+    (
+        signature::SIGNATURE_CHUNK_TYPE,
+        "Signature of the preceding chunk (edmipng)",
+    ),
+];
+
+/// Number of text bytes shown in summary of textual chunk
+const MAX_SUMMARY_TEXT_LEN: usize = 64;
+
+const RENDERING_INTENTS: [&str; 4] = [
+    "perceptual",
+    "relative colorimetric",
+    "saturation",
+    "absolute colorimetric",
+];
+
+/// Returns human description of chunk type, if it's a known one
+pub fn describe(chunk_type: &ChunkType) -> Option<&'static str> {
+    let chunk_type = chunk_type.to_string();
+    KNOWN_CHUNK_TYPES
+        .iter()
+        .find(|(known, _)| *known == chunk_type)
+        .map(|(_, description)| *description)
+}
+
+/// Decodes content of standard chunk into one line summary.
+/// Returns `None` for chunks without a summary, and a note if data is malformed.
+pub fn summarize(chunk_type: &ChunkType, data: &[u8]) -> Option<String> {
+    // Only small chunks are copied
+    let chunk = || Chunk::new(chunk_type.clone(), data.to_vec());
+    let summary = match chunk_type.to_string().as_str() {
+        "IHDR" => summarize_ihdr(&chunk()),
+        "PLTE" => Ok(format!("{} entries", data.len() / 3)),
+        "gAMA" => be_u32(data, 0).map(|gamma| format!("gamma {}", gamma as f64 / 100_000.0)),
+        "sRGB" => match data {
+            [intent] => RENDERING_INTENTS
+                .get(*intent as usize)
+                .map(|intent| format!("{} rendering intent", intent))
+                .ok_or_else(|| anyhow::Error::msg("unknown rendering intent")),
+            _ => bail_length(1),
+        },
+        "pHYs" => summarize_phys(data),
+        "tIME" => summarize_time(data),
+        "tEXt" | "zTXt" | "iTXt" => summarize_text(chunk_type, data),
+        chunk_type if chunk_type == signature::SIGNATURE_CHUNK_TYPE => match data.get(..4) {
+            Some(signed) => Ok(format!("signs {} chunk", String::from_utf8_lossy(signed))),
+            None => bail_length(4),
+        },
+        _ => return None,
+    };
+    Some(summary.unwrap_or_else(|err| format!("malformed data ({})", err)))
+}
+
+fn summarize_ihdr(chunk: &Chunk) -> Result<String> {
+    let ihdr = Ihdr::try_from(chunk)?;
+    let color_type = match ihdr.color_type {
+        ihdr::GREYSCALE => "greyscale",
+        ihdr::TRUECOLOR => "truecolor",
+        ihdr::INDEXED => "indexed",
+        ihdr::GREYSCALE_ALPHA => "greyscale with alpha",
+        ihdr::TRUECOLOR_ALPHA => "truecolor with alpha",
+        _ => "unknown color type",
+    };
+    let interlace = match ihdr.interlace_method {
+        0 => "non-interlaced",
+        _ => "interlaced",
+    };
+    Ok(format!(
+        "{}x{}, {}-bit {}, {}",
+        ihdr.width, ihdr.height, ihdr.bit_depth, color_type, interlace
+    ))
+}
+
+/// Keyword and beginning of text, with control characters escaped to keep summary in one line
+fn summarize_text(chunk_type: &ChunkType, data: &[u8]) -> Result<String> {
+    let preview = text::preview(chunk_type, data, MAX_SUMMARY_TEXT_LEN)?;
+    let mut summary = format!("{}: ", preview.keyword);
+    for c in preview.text.chars() {
+        if c.is_control() {
+            summary.extend(c.escape_default());
+        } else {
+            summary.push(c);
+        }
+    }
+    if preview.truncated {
+        summary.push_str("...");
+    }
+    Ok(summary)
+}
+
+fn summarize_phys(data: &[u8]) -> Result<String> {
+    if data.len() != 9 {
+        return bail_length(9);
+    }
+    let (x, y) = (be_u32(data, 0)?, be_u32(data, 4)?);
+    match data[8] {
+        // Unit is metre
+        1 => Ok(format!(
+            "{}x{} pixels per metre ({:.0}x{:.0} DPI)",
+            x,
+            y,
+            x as f64 * 0.0254,
+            y as f64 * 0.0254
+        )),
+        _ => Ok(format!("pixel aspect ratio {}:{}", x, y)),
+    }
+}
+
+fn summarize_time(data: &[u8]) -> Result<String> {
+    match data {
+        [year_high, year_low, month, day, hour, minute, second] => Ok(format!(
+            "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+            u16::from_be_bytes([*year_high, *year_low]),
+            month,
+            day,
+            hour,
+            minute,
+            second
+        )),
+        _ => bail_length(7),
+    }
+}
+
+fn be_u32(data: &[u8], offset: usize) -> Result<u32> {
+    match data.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_be_bytes(bytes.try_into()?)),
+        None => bail!("data is too short"),
+    }
+}
+
+fn bail_length<T>(expected: usize) -> Result<T> {
+    bail!("data should be {} bytes long", expected)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn summarize(chunk_type: &str, data: &[u8]) -> Option<String> {
+        super::summarize(&ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    #[test]
+    fn test_describe() {
+        let describe = |chunk_type| describe(&ChunkType::from_str(chunk_type).unwrap());
+        assert_eq!(describe("IDAT"), Some("Image data"));
+        assert_eq!(describe("pHYs"), Some("Physical pixel dimensions"));
+        assert_eq!(describe("ruSt"), None);
+    }
+
+    #[test]
+    fn test_summarize_standard_chunks() {
+        let ihdr = [0, 0, 1, 0, 0, 0, 0, 128, 8, 6, 0, 0, 0];
+        assert_eq!(
+            summarize("IHDR", &ihdr).unwrap(),
+            "256x128, 8-bit truecolor with alpha, non-interlaced"
+        );
+        assert_eq!(
+            summarize("gAMA", &45455u32.to_be_bytes()).unwrap(),
+            "gamma 0.45455"
+        );
+        assert_eq!(
+            summarize("pHYs", &[0, 0, 11, 19, 0, 0, 11, 19, 1]).unwrap(),
+            "2835x2835 pixels per metre (72x72 DPI)"
+        );
+        assert_eq!(
+            summarize("tIME", &[7, 232, 5, 1, 13, 37, 0]).unwrap(),
+            "2024-05-01 13:37:00 UTC"
+        );
+        assert_eq!(
+            summarize("sRGB", &[0]).unwrap(),
+            "perceptual rendering intent"
+        );
+        assert_eq!(summarize("tEXt", b"Title\0Cat").unwrap(), "Title: Cat");
+        assert_eq!(
+            summarize("tEXt", b"Comment\0first\nsecond").unwrap(),
+            "Comment: first\\nsecond"
+        );
+        let long = [b"Comment\0".as_slice(), &[b'a'; 100]].concat();
+        assert_eq!(
+            summarize("tEXt", &long).unwrap(),
+            format!("Comment: {}...", "a".repeat(MAX_SUMMARY_TEXT_LEN))
+        );
+        assert_eq!(summarize("IDAT", b"data"), None);
+        assert_eq!(summarize("ruSt", b"message"), None);
+    }
+
+    #[test]
+    fn test_summarize_malformed_chunks() {
+        assert_eq!(
+            summarize("tIME", &[7, 232]).unwrap(),
+            "malformed data (data should be 7 bytes long)"
+        );
+        assert_eq!(
+            summarize("gAMA", &[]).unwrap(),
+            "malformed data (data is too short)"
+        );
+    }
+}
@@ -0,0 +1,191 @@
+//! Ed25519 signatures of chunks, stored in `siGn` chunks right after the signed ones.
+
+use std::str::FromStr;
+
+use anyhow::{bail, Context, Error, Result};
+use ed25519_dalek::{Signature, Signer, SigningKey, VerifyingKey, SIGNATURE_LENGTH};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType, png::Png};
+
+// Signature is stored in companion chunk placed right after the signed chunk.
+// Layout of its data:
+// signed chunk type (4) | signer public key (32) | signature (64)
+/// Type of chunks holding signatures
+pub const SIGNATURE_CHUNK_TYPE: &str = "siGn";
+
+const CONTEXT: &[u8] = b"edmipng-signature-v1";
+const CHUNK_TYPE_LEN: usize = 4;
+const PUBLIC_KEY_LEN: usize = 32;
+/// Length of signature chunk data
+pub const SIGNATURE_DATA_LEN: usize = CHUNK_TYPE_LEN + PUBLIC_KEY_LEN + SIGNATURE_LENGTH;
+
+/// Result of checking single signature chunk
+pub struct SignatureCheck {
+    /// Index of signed chunk, or of the signature chunk itself if it's orphaned
+    pub index: usize,
+    /// Type of signed chunk, as stored in signature
+    pub chunk_type: String,
+    pub signer: Option<VerifyingKey>,
+    /// Signature doesn't follow chunk of the type it was made for (e.g. signed chunk was removed)
+    pub orphaned: bool,
+    pub result: Result<()>,
+}
+
+/// Creates companion chunk with detached signature over chunk type and data of given chunk
+pub fn sign_chunk(chunk: &Chunk, key: &SigningKey) -> Result<Chunk> {
+    let chunk_type_bytes = chunk.chunk_type().bytes();
+    let signature = key.sign(&signed_message(&chunk_type_bytes, chunk.data()));
+
+    let mut data: Vec<u8> = Vec::with_capacity(SIGNATURE_DATA_LEN);
+    data.extend(chunk_type_bytes);
+    data.extend(key.verifying_key().as_bytes());
+    data.extend(signature.to_bytes());
+
+    Ok(Chunk::new(ChunkType::from_str(SIGNATURE_CHUNK_TYPE)?, data))
+}
+
+/// Checks every signature chunk inside png file against the chunk preceding it
+pub fn verify_chunks(png: &Png) -> Vec<SignatureCheck> {
+    let chunks = png.chunks();
+    chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().to_string() == SIGNATURE_CHUNK_TYPE)
+        .map(|(index, signature_chunk)| {
+            let data = signature_chunk.data();
+            let chunk_type = data
+                .get(..CHUNK_TYPE_LEN)
+                .map(|bytes| String::from_utf8_lossy(bytes).into_owned())
+                .unwrap_or_default();
+            let signer = data
+                .get(CHUNK_TYPE_LEN..CHUNK_TYPE_LEN + PUBLIC_KEY_LEN)
+                .and_then(|bytes| VerifyingKey::try_from(bytes).ok());
+            let signed_chunk = index
+                .checked_sub(1)
+                .map(|signed_index| &chunks[signed_index])
+                .filter(|signed_chunk| signs(data, signed_chunk.chunk_type()));
+            let (signed_index, result) = match signed_chunk {
+                Some(signed_chunk) => (index - 1, verify_signature(data, signed_chunk)),
+                None => (
+                    index,
+                    Err(Error::msg(match index.checked_sub(1) {
+                        Some(previous) => format!(
+                            "Signature is for {} chunk, but it follows {} chunk",
+                            chunk_type,
+                            chunks[previous].chunk_type()
+                        ),
+                        None => String::from("Signature chunk doesn't follow any chunk"),
+                    })),
+                ),
+            };
+            SignatureCheck {
+                index: signed_index,
+                chunk_type,
+                signer,
+                orphaned: signed_chunk.is_none(),
+                result,
+            }
+        })
+        .collect()
+}
+
+/// Checks if signature chunk data was made for chunk with given type
+pub fn signs(data: &[u8], chunk_type: &ChunkType) -> bool {
+    data.len() == SIGNATURE_DATA_LEN && data[..CHUNK_TYPE_LEN] == chunk_type.bytes()
+}
+
+fn verify_signature(data: &[u8], signed_chunk: &Chunk) -> Result<()> {
+    if data.len() != SIGNATURE_DATA_LEN {
+        bail!(
+            "Signature chunk should be exactly {} bytes",
+            SIGNATURE_DATA_LEN
+        );
+    }
+    let (chunk_type_bytes, rest) = data.split_at(CHUNK_TYPE_LEN);
+    let (public_key, signature) = rest.split_at(PUBLIC_KEY_LEN);
+
+    if chunk_type_bytes != signed_chunk.chunk_type().bytes() {
+        bail!(
+            "Signature is for {} chunk, but it follows {} chunk",
+            String::from_utf8_lossy(chunk_type_bytes),
+            signed_chunk.chunk_type()
+        );
+    }
+    let public_key = VerifyingKey::try_from(public_key).context("Invalid signer public key")?;
+    let signature = Signature::from_slice(signature).context("Invalid signature")?;
+    public_key
+        .verify_strict(
+            &signed_message(chunk_type_bytes, signed_chunk.data()),
+            &signature,
+        )
+        .map_err(|_| Error::msg("Signature doesn't match chunk data"))
+}
+
+fn signed_message(chunk_type: &[u8], data: &[u8]) -> Vec<u8> {
+    CONTEXT
+        .iter()
+        .chain(chunk_type)
+        .chain(data)
+        .copied()
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunk_from_strings(chunk_type: &str, data: &str) -> Chunk {
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data.into())
+    }
+
+    fn signing_key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let key = signing_key(1);
+        let chunk = chunk_from_strings("ruSt", "Signed message");
+        let signature = sign_chunk(&chunk, &key).unwrap();
+        let png = Png::from_chunks(vec![chunk, signature]);
+
+        let checks = verify_chunks(&png);
+        assert_eq!(checks.len(), 1);
+        assert_eq!(checks[0].index, 0);
+        assert_eq!(checks[0].chunk_type, "ruSt");
+        assert_eq!(checks[0].signer, Some(key.verifying_key()));
+        assert!(checks[0].result.is_ok());
+    }
+
+    #[test]
+    fn test_verify_tampered_chunk() {
+        let chunk = chunk_from_strings("ruSt", "Signed message");
+        let signature = sign_chunk(&chunk, &signing_key(1)).unwrap();
+        let png = Png::from_chunks(vec![chunk_from_strings("ruSt", "Other message"), signature]);
+
+        let checks = verify_chunks(&png);
+        assert!(checks[0].result.is_err());
+    }
+
+    #[test]
+    fn test_verify_misplaced_signature() {
+        let chunk = chunk_from_strings("ruSt", "Signed message");
+        let signature = sign_chunk(&chunk, &signing_key(1)).unwrap();
+        let png = Png::from_chunks(vec![signature, chunk]);
+        let checks = verify_chunks(&png);
+        assert!(checks[0].orphaned);
+        assert!(checks[0].result.is_err());
+
+        let signature =
+            sign_chunk(&chunk_from_strings("ruSt", "Message"), &signing_key(1)).unwrap();
+        let png = Png::from_chunks(vec![chunk_from_strings("otHr", "Message"), signature]);
+        let checks = verify_chunks(&png);
+        assert!(checks[0].orphaned);
+        assert_eq!(checks[0].index, 1);
+        assert_eq!(checks[0].chunk_type, "ruSt");
+        assert_eq!(
+            checks[0].result.as_ref().err().unwrap().to_string(),
+            "Signature is for ruSt chunk, but it follows otHr chunk"
+        );
+    }
+}
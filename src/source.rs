@@ -1,29 +1,78 @@
+//! Where png file is read from: local path, url or standard input.
+
 use std::{
-    env, fs,
+    env,
+    fs::{self, File},
+    io::{self, BufReader, Read},
     path::PathBuf,
     str::FromStr,
     time::{SystemTime, UNIX_EPOCH},
 };
 
-use anyhow::{Error, Result};
-use reqwest::Url;
+use anyhow::{bail, Context, Error, Result};
+use reqwest::{blocking, Url};
+
+use crate::error::PngError;
 
+/// Location of png file
 pub enum Source {
     Path(PathBuf),
     Url(Url),
+    /// Standard input, given as `-`
+    Stdin,
 }
 
 impl Source {
     const DIRECTORY_ENV_KEY: &'static str = "EDMIPNG_DIR";
     const DEFAULT_FILE_NAME: &'static str = "png_file";
+    const STDIN_NAME: &'static str = "-";
+
+    /// Reads whole content of the file
+    pub fn read_bytes(&self) -> Result<Vec<u8>> {
+        match self {
+            Source::Path(path) => fs::read(path)
+                .map_err(PngError::Io)
+                .context("Couldn't load file."),
+            Source::Url(url) => Ok(Source::get(url)?.bytes().map_err(PngError::Http)?.to_vec()),
+            Source::Stdin => {
+                let mut bytes = Vec::new();
+                io::stdin()
+                    .read_to_end(&mut bytes)
+                    .map_err(PngError::Io)
+                    .context("Couldn't read standard input.")?;
+                Ok(bytes)
+            }
+        }
+    }
 
-    /// If source is path just returns it, otherwise return path to non-existing file with name based on url and date
+    /// Opens file for reading without loading it into memory.
+    /// Standard input can be opened only once, as it can't be read again.
+    pub fn open(&self) -> Result<Box<dyn Read>> {
+        match self {
+            Source::Path(path) => {
+                let file = File::open(path)
+                    .map_err(PngError::Io)
+                    .context("Couldn't load file.")?;
+                Ok(Box::new(BufReader::new(file)))
+            }
+            Source::Url(url) => Ok(Box::new(Source::get(url)?)),
+            Source::Stdin => Ok(Box::new(io::stdin().lock())),
+        }
+    }
+
+    /// Sends GET request, treating error status codes as failures
+    fn get(url: &Url) -> Result<blocking::Response, PngError> {
+        Ok(blocking::get(url.clone())?.error_for_status()?)
+    }
+
+    /// If source is path just returns it, otherwise return path to non-existing file with name based on url and date.
+    /// Fails for standard input, which has no file to be edited.
     pub fn get_output_file_path(&self) -> Result<PathBuf> {
         match self {
             Source::Path(path) => Ok(path.clone()),
             Source::Url(url) => {
                 let url_name = match url.path_segments() {
-                    Some(segments) => segments.last().unwrap_or(Source::DEFAULT_FILE_NAME),
+                    Some(mut segments) => segments.next_back().unwrap_or(Source::DEFAULT_FILE_NAME),
                     None => Source::DEFAULT_FILE_NAME,
                 };
                 let without_png_suffix = url_name.strip_suffix(".png").unwrap_or(url_name);
@@ -47,6 +96,7 @@ impl Source {
 
                 Ok(path)
             }
+            Source::Stdin => bail!("Output file has to be given when reading from standard input"),
         }
     }
 }
@@ -59,7 +109,11 @@ impl FromStr for Source {
     // we just check if given string points to exsiting file
     // - if it does -> it's a path
     // - if it doesn't -> we assume it's an url
+    // (`-` is standard input, as usual in command line tools)
     fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == Source::STDIN_NAME {
+            return Ok(Source::Stdin);
+        }
         let p = PathBuf::from(s);
         match p.try_exists() {
             Ok(true) => Ok(Source::Path(p)),
@@ -0,0 +1,786 @@
+//! Reading and writing png files chunk by chunk, without loading them into memory.
+
+use std::io::{self, Read, Write};
+
+use anyhow::{bail, Context, Error, Result};
+
+use crate::{
+    chunk::{Chunk, ChunkRef, CRC_32, LENGTH_FIELD_LEN, MAX_LENGTH},
+    chunk_type::ChunkType,
+    error::PngError,
+    ihdr::Ihdr,
+    png::{self, ChunkPosition, ChunkSelector},
+    signature,
+};
+
+/// Size of buffer used when copying chunk data
+const COPY_BUFFER_SIZE: usize = 64 * 1024;
+
+/// Length and type of chunk whose data wasn't read yet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkHeader {
+    /// Byte offset of the chunk in file
+    pub offset: u64,
+    pub length: u32,
+    pub chunk_type: ChunkType,
+}
+
+/// Reads png file chunk by chunk, validating CRC of every chunk while its data is read
+pub struct ChunkReader<R: Read> {
+    reader: R,
+    /// Number of bytes consumed so far
+    position: u64,
+    /// Header of chunk whose data wasn't consumed yet
+    pending: Option<ChunkHeader>,
+    /// Number of chunks whose headers were read
+    count: usize,
+    /// Set after IEND chunk or at the end of input, everything left is trailing data
+    finished: bool,
+    /// Bytes read while looking for next chunk, which turned out to be trailing data
+    trailing_start: Vec<u8>,
+    /// Whether first chunk has to be IHDR
+    require_ihdr: bool,
+}
+
+impl<R: Read> ChunkReader<R> {
+    /// Reads and checks png signature
+    pub fn new(mut reader: R) -> Result<ChunkReader<R>> {
+        let mut signature = [0; 8];
+        let read = read_up_to(&mut reader, &mut signature)?;
+        if signature[..read] != png::STANDARD_HEADER {
+            return Err(PngError::InvalidSignature.into());
+        }
+        Ok(ChunkReader {
+            reader,
+            position: signature.len() as u64,
+            pending: None,
+            count: 0,
+            finished: false,
+            trailing_start: Vec::new(),
+            require_ihdr: true,
+        })
+    }
+
+    /// Accepts any chunk as the first one, so that structure of the file can be checked separately
+    pub fn allow_any_first_chunk(mut self) -> ChunkReader<R> {
+        self.require_ihdr = false;
+        self
+    }
+
+    /// Reads header of the next chunk, skipping data of the current one if it wasn't consumed.
+    /// Returns None after IEND chunk or at the end of input.
+    pub fn next_header(&mut self) -> Result<Option<ChunkHeader>> {
+        if self.pending.is_some() {
+            self.skip_data()?;
+        }
+        if self.finished {
+            return Ok(None);
+        }
+
+        let offset = self.position;
+        let mut length = [0; LENGTH_FIELD_LEN];
+        let read = read_up_to(&mut self.reader, &mut length)?;
+        if read < LENGTH_FIELD_LEN {
+            // Too short to hold chunk length, kept as trailing data
+            self.finished = true;
+            self.trailing_start = length[..read].to_vec();
+            return Ok(None);
+        }
+        self.position += LENGTH_FIELD_LEN as u64;
+        let mut chunk_type = [0; 4];
+        self.read_field(&mut chunk_type, offset, "Chunk type", 4)?;
+        if self.require_ihdr && self.count == 0 && chunk_type != png::IHDR_CHUNK_TYPE {
+            return Err(PngError::MissingChunk {
+                chunk_type: String::from("IHDR"),
+                offset: Some(offset),
+            }
+            .into());
+        }
+        let length = u32::from_be_bytes(length);
+        if length > MAX_LENGTH {
+            return Err(PngError::InvalidLength {
+                offset,
+                field: "Data",
+                expected: length as u64,
+            }
+            .into());
+        }
+        let header = ChunkHeader {
+            offset,
+            length,
+            chunk_type: ChunkType::try_from(chunk_type).map_err(|_| {
+                PngError::InvalidChunkType {
+                    offset: Some(offset),
+                    value: chunk_type.to_vec(),
+                }
+            })?,
+        };
+        self.count += 1;
+        self.pending = Some(header.clone());
+        Ok(Some(header))
+    }
+
+    /// Number of chunks whose headers were read so far
+    pub fn chunk_count(&self) -> usize {
+        self.count
+    }
+
+    /// Reads data of the current chunk into memory
+    pub fn read_data(&mut self) -> Result<Chunk> {
+        let mut data = Vec::new();
+        let (header, crc) = self.stream_data(&mut data)?;
+        Ok(Chunk::from(ChunkRef::new(header.chunk_type, &data, crc)))
+    }
+
+    /// Skips data of the current chunk, still validating its CRC
+    pub fn skip_data(&mut self) -> Result<()> {
+        self.stream_data(&mut io::sink())?;
+        Ok(())
+    }
+
+    /// Copies the current chunk to writer without loading its data into memory.
+    /// IHDR chunk is also parsed, so that files with invalid header are refused.
+    pub fn copy_to<W: Write>(&mut self, writer: &mut ChunkWriter<W>) -> Result<()> {
+        let header = self
+            .pending
+            .clone()
+            .ok_or(Error::msg("There is no chunk to copy"))?;
+        if header.chunk_type.bytes() == png::IHDR_CHUNK_TYPE {
+            let chunk = self.read_data()?;
+            Ihdr::try_from(&chunk)?;
+            return writer.write_chunk(&chunk);
+        }
+
+        writer.write_header(header.length, &header.chunk_type)?;
+        let (_, crc) = self.stream_data(&mut writer.writer)?;
+        writer.write_raw(&crc.to_be_bytes())
+    }
+
+    /// Reads everything after the last chunk
+    pub fn read_trailing_data(&mut self) -> Result<Vec<u8>> {
+        let mut trailing_data = Vec::new();
+        self.copy_rest(&mut trailing_data)?;
+        Ok(trailing_data)
+    }
+
+    /// Copies everything after the last chunk to writer
+    pub fn copy_trailing_data<W: Write>(&mut self, writer: &mut ChunkWriter<W>) -> Result<()> {
+        self.copy_rest(&mut writer.writer)
+    }
+
+    fn copy_rest<O: Write>(&mut self, output: &mut O) -> Result<()> {
+        while self.next_header()?.is_some() {}
+        output
+            .write_all(&self.trailing_start)
+            .map_err(PngError::Io)
+            .context("Couldn't write png file.")?;
+        io::copy(&mut self.reader, output)
+            .map_err(PngError::Io)
+            .context("Couldn't copy trailing data.")?;
+        Ok(())
+    }
+
+    /// Passes data of the current chunk to output, returning its header and CRC once it's validated
+    fn stream_data<O: Write>(&mut self, output: &mut O) -> Result<(ChunkHeader, u32)> {
+        let header = self.take_pending()?;
+        let mut digest = CRC_32.digest();
+        digest.update(&header.chunk_type.bytes());
+
+        let mut buffer = vec![0; COPY_BUFFER_SIZE.min(header.length as usize)];
+        let mut left = header.length as usize;
+        while left > 0 {
+            let part_len = left.min(COPY_BUFFER_SIZE);
+            let part = &mut buffer[..part_len];
+            self.read_field(part, header.offset, "Data", header.length as u64)?;
+            digest.update(part);
+            output
+                .write_all(part)
+                .map_err(PngError::Io)
+                .context("Couldn't write png file.")?;
+            left -= part_len;
+        }
+
+        let mut crc = [0; 4];
+        self.read_field(&mut crc, header.offset, "Crc", 4)?;
+        let crc = u32::from_be_bytes(crc);
+        let expected = digest.finalize();
+        if crc != expected {
+            return Err(PngError::CrcMismatch {
+                offset: header.offset,
+                chunk_type: header.chunk_type.to_string(),
+                expected,
+                actual: crc,
+            }
+            .into());
+        }
+        Ok((header, crc))
+    }
+
+    /// Fills buffer with part of chunk field, failing if input ends before it
+    fn read_field(
+        &mut self,
+        buffer: &mut [u8],
+        offset: u64,
+        field: &'static str,
+        expected: u64,
+    ) -> Result<(), PngError> {
+        self.reader
+            .read_exact(buffer)
+            .map_err(|error| match error.kind() {
+                io::ErrorKind::UnexpectedEof => PngError::InvalidLength {
+                    offset,
+                    field,
+                    expected,
+                },
+                _ => PngError::Io(error),
+            })?;
+        self.position += buffer.len() as u64;
+        Ok(())
+    }
+
+    fn take_pending(&mut self) -> Result<ChunkHeader> {
+        let header = self
+            .pending
+            .take()
+            .ok_or(Error::msg("There is no chunk to read"))?;
+        if header.chunk_type.bytes() == png::IEND_CHUNK_TYPE {
+            self.finished = true;
+        }
+        Ok(header)
+    }
+}
+
+impl<R: Read> Iterator for ChunkReader<R> {
+    type Item = Result<Chunk>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let chunk = match self.next_header() {
+            Ok(Some(_)) => self.read_data(),
+            Ok(None) => return None,
+            Err(error) => Err(error),
+        };
+        // Input can't be read any further after an error
+        if chunk.is_err() {
+            self.finished = true;
+        }
+        Some(chunk)
+    }
+}
+
+/// Writes png file chunk by chunk
+pub struct ChunkWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> ChunkWriter<W> {
+    /// Writes png signature
+    pub fn new(mut writer: W) -> Result<ChunkWriter<W>> {
+        writer
+            .write_all(&png::STANDARD_HEADER)
+            .map_err(PngError::Io)
+            .context("Couldn't write png file.")?;
+        Ok(ChunkWriter { writer })
+    }
+
+    /// Writes whole chunk
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> Result<()> {
+        self.write_header(chunk.length(), chunk.chunk_type())?;
+        self.write_raw(chunk.data())?;
+        self.write_raw(&chunk.crc().to_be_bytes())
+    }
+
+    /// Writes bytes as they are (used for data after the last chunk)
+    pub fn write_raw(&mut self, bytes: &[u8]) -> Result<()> {
+        self.writer
+            .write_all(bytes)
+            .map_err(PngError::Io)
+            .context("Couldn't write png file.")
+    }
+
+    /// Flushes and returns underlying writer
+    pub fn finish(mut self) -> Result<W> {
+        self.writer
+            .flush()
+            .map_err(PngError::Io)
+            .context("Couldn't write png file.")?;
+        Ok(self.writer)
+    }
+
+    fn write_header(&mut self, length: u32, chunk_type: &ChunkType) -> Result<()> {
+        self.write_raw(&length.to_be_bytes())?;
+        self.write_raw(&chunk_type.bytes())
+    }
+}
+
+/// Copies png file from reader to writer, inserting chunks at given position.
+/// Position `After` has to be resolved into `Index` first (see [`index_after`]).
+/// Placement can depend on chunks after the inserted ones, so it's only checked once the whole file
+/// was copied, and output has to be discarded on error.
+pub fn insert_chunks<R: Read, W: Write>(
+    reader: &mut ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunks: Vec<Chunk>,
+    position: &ChunkPosition,
+) -> Result<()> {
+    let inserted: Vec<ChunkType> = chunks
+        .iter()
+        .map(|chunk| chunk.chunk_type().clone())
+        .collect();
+    let mut chunks = Some(chunks);
+    let mut chunk_types = Vec::new();
+    let mut color_type = None;
+    let mut index = 0;
+    while let Some(header) = reader.next_header()? {
+        let insert_here = match position {
+            ChunkPosition::BeforeIend => header.chunk_type.bytes() == png::IEND_CHUNK_TYPE,
+            ChunkPosition::Before(chunk_type) => &header.chunk_type == chunk_type,
+            ChunkPosition::Index(index) => reader.chunk_count() == index + 1,
+            ChunkPosition::After(_) => bail!("Position after chunk must be resolved to index"),
+        };
+        if insert_here {
+            if let Some(chunks) = chunks.take() {
+                index = chunk_types.len();
+                write_chunks(writer, chunks)?;
+            }
+        }
+        if header.chunk_type.bytes() == png::IHDR_CHUNK_TYPE {
+            let chunk = reader.read_data()?;
+            color_type = Some(Ihdr::try_from(&chunk)?.color_type);
+            writer.write_chunk(&chunk)?;
+        } else {
+            reader.copy_to(writer)?;
+        }
+        chunk_types.push(header.chunk_type);
+    }
+
+    if let Some(chunks) = chunks {
+        match position {
+            ChunkPosition::BeforeIend => {}
+            ChunkPosition::Index(index) if *index == reader.chunk_count() => {}
+            ChunkPosition::Index(index) => bail!(
+                "Index {} is out of range (file has {} chunks)",
+                index,
+                reader.chunk_count()
+            ),
+            ChunkPosition::Before(chunk_type) | ChunkPosition::After(chunk_type) => {
+                return Err(png::missing_chunk(chunk_type))
+            }
+        }
+        index = chunk_types.len();
+        write_chunks(writer, chunks)?;
+    }
+    png::check_placement(&chunk_types, index..index, &inserted, color_type)
+}
+
+fn write_chunks<W: Write>(writer: &mut ChunkWriter<W>, chunks: Vec<Chunk>) -> Result<()> {
+    for chunk in chunks {
+        writer.write_chunk(&chunk)?;
+    }
+    Ok(())
+}
+
+/// Returns index right after the last chunk with given type
+pub fn index_after<R: Read>(reader: &mut ChunkReader<R>, chunk_type: &ChunkType) -> Result<usize> {
+    let mut index = None;
+    while let Some(header) = reader.next_header()? {
+        if &header.chunk_type == chunk_type {
+            index = Some(reader.chunk_count());
+        }
+    }
+    index.ok_or_else(|| png::missing_chunk(chunk_type))
+}
+
+/// Copies png file from reader to writer, leaving out the first chunk with given type
+pub fn remove_first_chunk<R: Read, W: Write>(
+    reader: &mut ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunk_type: &ChunkType,
+) -> Result<()> {
+    remove_chunks(reader, writer, chunk_type, ChunkSelector::First).map(|_| ())
+}
+
+/// Copies chunks from reader to writer, skipping selected chunks with given type
+/// together with signature chunks right after them, which would become orphaned.
+/// Returns number of removed chunks (without signatures).
+pub fn remove_chunks<R: Read, W: Write>(
+    reader: &mut ChunkReader<R>,
+    writer: &mut ChunkWriter<W>,
+    chunk_type: &ChunkType,
+    selector: ChunkSelector,
+) -> Result<usize> {
+    let mut found = 0;
+    let mut removed = 0;
+    let mut previous_removed = false;
+    while let Some(header) = reader.next_header()? {
+        if previous_removed
+            && header.chunk_type.to_string() == signature::SIGNATURE_CHUNK_TYPE
+            && header.length as usize == signature::SIGNATURE_DATA_LEN
+        {
+            previous_removed = false;
+            let signature_chunk = reader.read_data()?;
+            if !signature::signs(signature_chunk.data(), chunk_type) {
+                writer.write_chunk(&signature_chunk)?;
+            }
+            continue;
+        }
+        previous_removed = false;
+        if &header.chunk_type != chunk_type {
+            reader.copy_to(writer)?;
+            continue;
+        }
+        if selector.matches(reader.chunk_count() - 1, found) {
+            reader.skip_data()?;
+            removed += 1;
+            previous_removed = true;
+        } else {
+            reader.copy_to(writer)?;
+        }
+        found += 1;
+    }
+    if removed == 0 {
+        return Err(png::missing_chunk(chunk_type));
+    }
+    Ok(removed)
+}
+
+/// Fills buffer as much as possible, returning number of read bytes (less than buffer length only at the end of input)
+fn read_up_to<R: Read>(reader: &mut R, buffer: &mut [u8]) -> Result<usize> {
+    let mut read = 0;
+    while read < buffer.len() {
+        match reader.read(&mut buffer[read..]) {
+            Ok(0) => break,
+            Ok(count) => read += count,
+            Err(error) if error.kind() == io::ErrorKind::Interrupted => {}
+            Err(error) => return Err(PngError::Io(error)).context("Couldn't read png file."),
+        }
+    }
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        decoder::{Image, SampleBuffer},
+        encoder, ihdr,
+        png::Png,
+    };
+    use ed25519_dalek::SigningKey;
+    use std::str::FromStr;
+
+    fn testing_png() -> Png {
+        let image = Image {
+            width: 16,
+            height: 16,
+            bit_depth: 8,
+            color_type: ihdr::GREYSCALE,
+            samples: SampleBuffer::U8((0..=255).collect()),
+            palette: None,
+        };
+        let mut png = encoder::encode(&image, 10).unwrap();
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"message".to_vec());
+        png.insert_chunk(chunk, ChunkPosition::Index(1)).unwrap();
+        png
+    }
+
+    fn testing_bytes() -> Vec<u8> {
+        let mut bytes = testing_png().as_bytes();
+        bytes.extend(b"trailing data");
+        bytes
+    }
+
+    fn testing_chunk() -> Chunk {
+        Chunk::new(ChunkType::from_str("teSt").unwrap(), b"inserted".to_vec())
+    }
+
+    /// Reader returning at most one byte at a time
+    struct ByteByByte<'a>(&'a [u8]);
+
+    impl Read for ByteByByte<'_> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let count = buf.len().min(self.0.len()).min(1);
+            buf[..count].copy_from_slice(&self.0[..count]);
+            self.0 = &self.0[count..];
+            Ok(count)
+        }
+    }
+
+    fn rewrite<F>(bytes: &[u8], edit: F) -> Result<Vec<u8>>
+    where
+        F: FnOnce(&mut ChunkReader<&[u8]>, &mut ChunkWriter<Vec<u8>>) -> Result<()>,
+    {
+        let mut reader = ChunkReader::new(bytes)?;
+        let mut writer = ChunkWriter::new(Vec::new())?;
+        edit(&mut reader, &mut writer)?;
+        reader.copy_trailing_data(&mut writer)?;
+        writer.finish()
+    }
+
+    #[test]
+    fn test_read_chunks() {
+        let bytes = testing_bytes();
+        let mut reader = ChunkReader::new(ByteByByte(&bytes)).unwrap();
+        let chunks = reader.by_ref().collect::<Result<Vec<Chunk>>>().unwrap();
+        let expected = testing_png();
+        assert_eq!(chunks.len(), expected.chunks().len());
+        for (chunk, expected) in chunks.iter().zip(expected.chunks()) {
+            assert_eq!(chunk.as_bytes(), expected.as_bytes());
+        }
+        assert_eq!(reader.read_trailing_data().unwrap(), b"trailing data");
+    }
+
+    #[test]
+    fn test_copy_is_byte_exact() {
+        let bytes = testing_bytes();
+        let copied = rewrite(&bytes, |reader, writer| {
+            while reader.next_header()?.is_some() {
+                reader.copy_to(writer)?;
+            }
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(copied, bytes);
+    }
+
+    #[test]
+    fn test_invalid_crc() {
+        let mut bytes = testing_bytes();
+        // Last byte of ruSt chunk CRC
+        bytes[33 + 19 - 1] ^= 0xFF;
+        let error = rewrite(&bytes, |reader, _| {
+            while reader.next_header()?.is_some() {}
+            Ok(())
+        })
+        .err()
+        .unwrap();
+        match error.downcast_ref::<PngError>() {
+            Some(PngError::CrcMismatch {
+                offset,
+                chunk_type,
+                expected,
+                actual,
+            }) => {
+                assert_eq!(*offset, 33);
+                assert_eq!(chunk_type, "ruSt");
+                assert_eq!(expected ^ actual, 0xFF);
+            }
+            _ => panic!("Unexpected error: {}", error),
+        }
+    }
+
+    #[test]
+    fn test_invalid_files() {
+        let bytes = testing_bytes();
+        assert!(ChunkReader::new(&bytes[..5]).is_err());
+
+        // Cut in the middle of ruSt chunk data
+        let truncated = &bytes[..33 + 10];
+        let error = ChunkReader::new(truncated)
+            .unwrap()
+            .collect::<Result<Vec<Chunk>>>()
+            .err()
+            .unwrap();
+        assert_eq!(
+            error.to_string(),
+            "Data should be exactly 7 bytes (chunk at byte 33)"
+        );
+
+        let mut too_long = bytes[..33].to_vec();
+        too_long.extend(0x8000_0000u32.to_be_bytes());
+        too_long.extend(b"ruSt");
+        let error = ChunkReader::new(too_long.as_slice())
+            .unwrap()
+            .collect::<Result<Vec<Chunk>>>()
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<PngError>(),
+            Some(PngError::InvalidLength {
+                offset: 33,
+                expected: 0x8000_0000,
+                ..
+            })
+        ));
+
+        let mut without_ihdr = bytes[..8].to_vec();
+        without_ihdr.extend(testing_chunk().as_bytes());
+        let error = ChunkReader::new(without_ihdr.as_slice())
+            .unwrap()
+            .next()
+            .unwrap()
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error.downcast_ref::<PngError>(),
+            Some(PngError::MissingChunk { chunk_type, offset: Some(8) }) if chunk_type == "IHDR"
+        ));
+        let chunks = ChunkReader::new(without_ihdr.as_slice())
+            .unwrap()
+            .allow_any_first_chunk()
+            .collect::<Result<Vec<Chunk>>>()
+            .unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(chunks[0].as_bytes(), testing_chunk().as_bytes());
+    }
+
+    #[test]
+    fn test_insert_chunks() {
+        let bytes = testing_bytes();
+        let after_rust = ChunkPosition::Index(
+            index_after(
+                &mut ChunkReader::new(bytes.as_slice()).unwrap(),
+                &ChunkType::from_str("ruSt").unwrap(),
+            )
+            .unwrap(),
+        );
+        for (position, expected) in [
+            (ChunkPosition::BeforeIend, ChunkPosition::BeforeIend),
+            (
+                ChunkPosition::Before(ChunkType::from_str("IDAT").unwrap()),
+                ChunkPosition::Index(2),
+            ),
+            (ChunkPosition::Index(1), ChunkPosition::Index(1)),
+            (after_rust, ChunkPosition::Index(2)),
+        ] {
+            let inserted = rewrite(&bytes, |reader, writer| {
+                insert_chunks(reader, writer, vec![testing_chunk()], &position)
+            })
+            .unwrap();
+            let mut png = Png::try_from(bytes.as_slice()).unwrap();
+            png.insert_chunk(testing_chunk(), expected).unwrap();
+            assert_eq!(inserted, png.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_insert_chunks_errors() {
+        let bytes = testing_bytes();
+        let insert = |position: ChunkPosition| {
+            rewrite(&bytes, |reader, writer| {
+                insert_chunks(reader, writer, vec![testing_chunk()], &position)
+            })
+            .err()
+            .unwrap()
+            .to_string()
+        };
+        assert_eq!(
+            insert(ChunkPosition::Index(0)),
+            "Chunk can't be placed before IHDR chunk"
+        );
+        assert_eq!(
+            insert(ChunkPosition::Index(3)),
+            "Chunk can't be placed between consecutive IDAT chunks"
+        );
+        assert!(insert(ChunkPosition::Index(100)).starts_with("Index 100 is out of range"));
+        assert_eq!(
+            insert(ChunkPosition::Before(ChunkType::from_str("tIME").unwrap())),
+            "Chunk with type tIME doesn't exist"
+        );
+
+        // Whole file is needed to check ordering rules
+        let insert_type = |chunk_type: &str, position: ChunkPosition| {
+            let chunk = Chunk::new(ChunkType::from_str(chunk_type).unwrap(), vec![0; 4]);
+            rewrite(&bytes, |reader, writer| {
+                insert_chunks(reader, writer, vec![chunk], &position)
+            })
+            .map_err(|err| err.to_string())
+        };
+        assert_eq!(
+            insert_type("gAMA", ChunkPosition::BeforeIend).unwrap_err(),
+            "Chunk placement would make png file invalid: gAMA: must appear before IDAT"
+        );
+        assert_eq!(
+            insert_type("PLTE", ChunkPosition::Index(1)).unwrap_err(),
+            "Chunk placement would make png file invalid: PLTE: not allowed in greyscale images"
+        );
+        assert!(insert_type("gAMA", ChunkPosition::Index(1)).is_ok());
+    }
+
+    #[test]
+    fn test_remove_first_chunk() {
+        let bytes = testing_bytes();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let removed = rewrite(&bytes, |reader, writer| {
+            remove_first_chunk(reader, writer, &chunk_type)
+        })
+        .unwrap();
+        let mut png = Png::try_from(bytes.as_slice()).unwrap();
+        png.remove_first_chunk("ruSt").unwrap();
+        assert_eq!(removed, png.as_bytes());
+
+        assert!(rewrite(&removed, |reader, writer| {
+            remove_first_chunk(reader, writer, &chunk_type)
+        })
+        .is_err());
+    }
+
+    #[test]
+    fn test_remove_signed_chunk() {
+        let key = SigningKey::from_bytes(&[1; 32]);
+        let mut png = testing_png();
+        for message in ["first", "second"] {
+            let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), message.into());
+            let signature_chunk = signature::sign_chunk(&chunk, &key).unwrap();
+            png.insert_chunk(chunk, ChunkPosition::BeforeIend).unwrap();
+            png.insert_chunk(signature_chunk, ChunkPosition::BeforeIend)
+                .unwrap();
+        }
+        let bytes = png.as_bytes();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let rust_count = png.chunks_by_type("ruSt").len();
+
+        let removed = rewrite(&bytes, |reader, writer| {
+            remove_chunks(
+                reader,
+                writer,
+                &chunk_type,
+                ChunkSelector::Nth(rust_count - 2),
+            )
+            .map(|_| ())
+        })
+        .unwrap();
+        let png = Png::try_from(removed.as_slice()).unwrap();
+        assert_eq!(png.chunks_by_type("siGn").len(), 1);
+        let checks = signature::verify_chunks(&png);
+        assert_eq!(checks.len(), 1);
+        assert!(!checks[0].orphaned);
+        assert!(checks[0].result.is_ok());
+        assert_eq!(png.chunks()[checks[0].index].data(), b"second");
+    }
+
+    #[test]
+    fn test_remove_chunks() {
+        let mut png = testing_png();
+        for message in ["second", "third"] {
+            let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), message.into());
+            png.insert_chunk(chunk, ChunkPosition::BeforeIend).unwrap();
+        }
+        let bytes = png.as_bytes();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let last_index = png.chunks().len() - 2;
+
+        for selector in [
+            ChunkSelector::All,
+            ChunkSelector::Nth(1),
+            ChunkSelector::Index(last_index),
+        ] {
+            let mut count = 0;
+            let removed = rewrite(&bytes, |reader, writer| {
+                count = remove_chunks(reader, writer, &chunk_type, selector)?;
+                Ok(())
+            })
+            .unwrap();
+            let mut expected = Png::try_from(bytes.as_slice()).unwrap();
+            let expected_count = expected.remove_chunks("ruSt", selector).unwrap().len();
+            assert_eq!(count, expected_count);
+            assert_eq!(removed, expected.as_bytes());
+        }
+
+        for selector in [ChunkSelector::Nth(3), ChunkSelector::Index(2)] {
+            assert!(rewrite(&bytes, |reader, writer| {
+                remove_chunks(reader, writer, &chunk_type, selector).map(|_| ())
+            })
+            .is_err());
+        }
+    }
+}
@@ -0,0 +1,467 @@
+//! Textual chunks (tEXt, zTXt and iTXt).
+
+use std::{
+    io::{Read, Write},
+    str::FromStr,
+};
+
+use anyhow::{bail, Context, Error, Result};
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+
+use crate::{chunk::Chunk, chunk_type::ChunkType};
+
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Anc-text
+pub const TEXT_CHUNK_TYPE: &str = "tEXt";
+pub const COMPRESSED_TEXT_CHUNK_TYPE: &str = "zTXt";
+pub const INTERNATIONAL_TEXT_CHUNK_TYPE: &str = "iTXt";
+
+const MAX_KEYWORD_LEN: usize = 79;
+/// Longest text inflated from zTXt and iTXt chunks, so that small chunk can't take all memory
+pub const MAX_TEXT_LEN: usize = 16 * 1024 * 1024;
+const SEPARATOR: u8 = 0;
+const DEFLATE_METHOD: u8 = 0;
+
+/// Standard textual chunk
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum TextChunk {
+    /// tEXt - keyword and Latin-1 text
+    Text { keyword: String, text: String },
+    /// zTXt - keyword and zlib-compressed Latin-1 text
+    Compressed { keyword: String, text: String },
+    /// iTXt - keyword and UTF-8 text (optionally compressed) with language tag and translated keyword
+    International {
+        keyword: String,
+        compressed: bool,
+        language_tag: String,
+        translated_keyword: String,
+        text: String,
+    },
+}
+
+impl TextChunk {
+    /// Checks if chunk type is one of the textual chunk types
+    pub fn is_text_chunk_type(chunk_type: &ChunkType) -> bool {
+        let chunk_type = chunk_type.to_string();
+        chunk_type == TEXT_CHUNK_TYPE
+            || chunk_type == COMPRESSED_TEXT_CHUNK_TYPE
+            || chunk_type == INTERNATIONAL_TEXT_CHUNK_TYPE
+    }
+
+    pub fn keyword(&self) -> &str {
+        match self {
+            TextChunk::Text { keyword, .. }
+            | TextChunk::Compressed { keyword, .. }
+            | TextChunk::International { keyword, .. } => keyword,
+        }
+    }
+
+    pub fn text(&self) -> &str {
+        match self {
+            TextChunk::Text { text, .. }
+            | TextChunk::Compressed { text, .. }
+            | TextChunk::International { text, .. } => text,
+        }
+    }
+
+    pub fn chunk_type(&self) -> &'static str {
+        match self {
+            TextChunk::Text { .. } => TEXT_CHUNK_TYPE,
+            TextChunk::Compressed { .. } => COMPRESSED_TEXT_CHUNK_TYPE,
+            TextChunk::International { .. } => INTERNATIONAL_TEXT_CHUNK_TYPE,
+        }
+    }
+
+    /// Builds png chunk, validating keyword and text encoding
+    pub fn to_chunk(&self) -> Result<Chunk> {
+        let mut data = encode_keyword(self.keyword())?;
+        data.push(SEPARATOR);
+        match self {
+            TextChunk::Text { text, .. } => {
+                data.extend(encode_latin1(text)?);
+            }
+            TextChunk::Compressed { text, .. } => {
+                data.push(DEFLATE_METHOD);
+                data.extend(compress(&encode_latin1(text)?)?);
+            }
+            TextChunk::International {
+                compressed,
+                language_tag,
+                translated_keyword,
+                text,
+                ..
+            } => {
+                if !language_tag
+                    .bytes()
+                    .all(|byte| byte.is_ascii_alphanumeric() || byte == b'-')
+                {
+                    bail!("Language tag can only contain ascii letters, digits and hyphens");
+                }
+                if translated_keyword.contains('\0') || text.contains('\0') {
+                    bail!("Translated keyword and text can't contain null character");
+                }
+                data.push(*compressed as u8);
+                data.push(DEFLATE_METHOD);
+                data.extend(language_tag.as_bytes());
+                data.push(SEPARATOR);
+                data.extend(translated_keyword.as_bytes());
+                data.push(SEPARATOR);
+                if *compressed {
+                    data.extend(compress(text.as_bytes())?);
+                } else {
+                    data.extend(text.as_bytes());
+                }
+            }
+        }
+        Ok(Chunk::new(ChunkType::from_str(self.chunk_type())?, data))
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = Error;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        let raw = RawText::parse(chunk.chunk_type(), chunk.data())?;
+        let text = if raw.compressed {
+            decompress(raw.text)?
+        } else {
+            raw.text.to_vec()
+        };
+        let keyword = decode_latin1(raw.keyword);
+
+        match raw.international {
+            None if raw.compressed => Ok(TextChunk::Compressed {
+                keyword,
+                text: decode_latin1(&text),
+            }),
+            None => Ok(TextChunk::Text {
+                keyword,
+                text: decode_latin1(&text),
+            }),
+            Some((language_tag, translated_keyword)) => Ok(TextChunk::International {
+                keyword,
+                compressed: raw.compressed,
+                language_tag: String::from_utf8(language_tag.to_vec())
+                    .context("Language tag is not valid utf-8")?,
+                translated_keyword: String::from_utf8(translated_keyword.to_vec())
+                    .context("Translated keyword is not valid utf-8")?,
+                text: String::from_utf8(text).context("Text is not valid utf-8")?,
+            }),
+        }
+    }
+}
+
+/// Keyword and beginning of text of textual chunk
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextPreview {
+    pub keyword: String,
+    pub text: String,
+    /// Whether text is longer than the preview
+    pub truncated: bool,
+}
+
+/// Reads keyword and at most `max_len` first bytes of text, inflating only as much
+/// compressed text as needed, so that previews of huge chunks stay cheap
+pub fn preview(chunk_type: &ChunkType, data: &[u8], max_len: usize) -> Result<TextPreview> {
+    let raw = RawText::parse(chunk_type, data)?;
+    let mut text = Vec::new();
+    if raw.compressed {
+        ZlibDecoder::new(raw.text)
+            .take(max_len as u64 + 1)
+            .read_to_end(&mut text)
+            .context("Couldn't decompress text")?;
+    } else {
+        text.extend(raw.text.iter().take(max_len + 1));
+    }
+    let truncated = text.len() > max_len;
+    text.truncate(max_len);
+
+    let text = match raw.international {
+        None => decode_latin1(&text),
+        // Prefix can end in the middle of a character, which isn't an error
+        Some(_) => match std::str::from_utf8(&text) {
+            Ok(text) => text.to_string(),
+            Err(err) if err.error_len().is_none() => {
+                String::from_utf8_lossy(&text[..err.valid_up_to()]).into_owned()
+            }
+            Err(_) => bail!("Text is not valid utf-8"),
+        },
+    };
+    Ok(TextPreview {
+        keyword: decode_latin1(raw.keyword),
+        text,
+        truncated,
+    })
+}
+
+/// Fields of textual chunk, with text still compressed
+struct RawText<'a> {
+    keyword: &'a [u8],
+    compressed: bool,
+    /// Language tag and translated keyword of iTXt chunk
+    international: Option<(&'a [u8], &'a [u8])>,
+    text: &'a [u8],
+}
+
+impl<'a> RawText<'a> {
+    fn parse(chunk_type: &ChunkType, data: &'a [u8]) -> Result<RawText<'a>> {
+        let chunk_type = chunk_type.to_string();
+        let (keyword, rest) =
+            split_at_separator(data).ok_or(Error::msg("Keyword is not null-terminated"))?;
+
+        match chunk_type.as_str() {
+            TEXT_CHUNK_TYPE => Ok(RawText {
+                keyword,
+                compressed: false,
+                international: None,
+                text: rest,
+            }),
+            COMPRESSED_TEXT_CHUNK_TYPE => {
+                let (method, text) = rest
+                    .split_first()
+                    .ok_or(Error::msg("Missing compression method"))?;
+                check_compression_method(*method)?;
+                Ok(RawText {
+                    keyword,
+                    compressed: true,
+                    international: None,
+                    text,
+                })
+            }
+            INTERNATIONAL_TEXT_CHUNK_TYPE => {
+                let [flag, method, rest @ ..] = rest else {
+                    bail!("Missing compression flag and method");
+                };
+                let (language_tag, rest) = split_at_separator(rest)
+                    .ok_or(Error::msg("Language tag is not null-terminated"))?;
+                let (translated_keyword, text) = split_at_separator(rest)
+                    .ok_or(Error::msg("Translated keyword is not null-terminated"))?;
+                let compressed = match flag {
+                    0 => false,
+                    1 => true,
+                    flag => bail!("Invalid compression flag: {}", flag),
+                };
+                if compressed {
+                    check_compression_method(*method)?;
+                }
+                Ok(RawText {
+                    keyword,
+                    compressed,
+                    international: Some((language_tag, translated_keyword)),
+                    text,
+                })
+            }
+            _ => bail!("Chunk {} is not a text chunk", chunk_type),
+        }
+    }
+}
+
+fn split_at_separator(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    let index = bytes.iter().position(|byte| *byte == SEPARATOR)?;
+    Some((&bytes[..index], &bytes[index + 1..]))
+}
+
+fn check_compression_method(method: u8) -> Result<()> {
+    if method != DEFLATE_METHOD {
+        bail!("Unsupported compression method: {}", method);
+    }
+    Ok(())
+}
+
+fn encode_keyword(keyword: &str) -> Result<Vec<u8>> {
+    let bytes = encode_latin1(keyword).context("Invalid keyword")?;
+    if bytes.is_empty() || bytes.len() > MAX_KEYWORD_LEN {
+        bail!(
+            "Keyword must be between 1 and {} characters long",
+            MAX_KEYWORD_LEN
+        );
+    }
+    if bytes
+        .iter()
+        .any(|byte| !matches!(byte, 32..=126 | 161..=255))
+    {
+        bail!("Keyword can only contain printable Latin-1 characters");
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        bail!("Keyword can't have leading, trailing or consecutive spaces");
+    }
+    Ok(bytes)
+}
+
+/// Checks if text can be stored in tEXt or zTXt chunk
+pub fn is_latin1(text: &str) -> bool {
+    text.chars().all(|c| c as u32 <= 0xFF && c != '\0')
+}
+
+fn encode_latin1(text: &str) -> Result<Vec<u8>> {
+    if !is_latin1(text) {
+        bail!("Text contains characters that can't be encoded in Latin-1");
+    }
+    Ok(text.chars().map(|c| c as u8).collect())
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| *byte as char).collect()
+}
+
+fn compress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes).context("Couldn't compress text")?;
+    encoder.finish().context("Couldn't compress text")
+}
+
+/// Inflates text, refusing to produce more than `MAX_TEXT_LEN` bytes
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>> {
+    let mut decompressed = Vec::new();
+    ZlibDecoder::new(bytes)
+        .take(MAX_TEXT_LEN as u64 + 1)
+        .read_to_end(&mut decompressed)
+        .context("Couldn't decompress text")?;
+    if decompressed.len() > MAX_TEXT_LEN {
+        bail!(
+            "Decompressed text is larger than allowed {} bytes",
+            MAX_TEXT_LEN
+        );
+    }
+    Ok(decompressed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(text_chunk: TextChunk) {
+        let chunk = text_chunk.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), text_chunk.chunk_type());
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text_chunk);
+    }
+
+    #[test]
+    fn test_text_round_trip() {
+        round_trip(TextChunk::Text {
+            keyword: String::from("Author"),
+            text: String::from("Zoë Kowalska"),
+        });
+    }
+
+    #[test]
+    fn test_compressed_text_round_trip() {
+        round_trip(TextChunk::Compressed {
+            keyword: String::from("Comment"),
+            text: "Compressed ".repeat(100),
+        });
+    }
+
+    #[test]
+    fn test_international_text_round_trip() {
+        for compressed in [false, true] {
+            round_trip(TextChunk::International {
+                keyword: String::from("Title"),
+                compressed,
+                language_tag: String::from("pl-PL"),
+                translated_keyword: String::from("Tytuł"),
+                text: String::from("Zażółć gęślą jaźń"),
+            });
+        }
+    }
+
+    #[test]
+    fn test_preview() {
+        let preview_of = |text_chunk: TextChunk, max_len| {
+            let chunk = text_chunk.to_chunk().unwrap();
+            preview(chunk.chunk_type(), chunk.data(), max_len).unwrap()
+        };
+        let preview = preview_of(
+            TextChunk::Compressed {
+                keyword: String::from("Comment"),
+                text: "Compressed ".repeat(100),
+            },
+            14,
+        );
+        assert_eq!(preview.keyword, "Comment");
+        assert_eq!(preview.text, "Compressed Com");
+        assert!(preview.truncated);
+
+        // Multi-byte character cut in half is dropped
+        let preview = preview_of(
+            TextChunk::International {
+                keyword: String::from("Title"),
+                compressed: true,
+                language_tag: String::new(),
+                translated_keyword: String::new(),
+                text: String::from("Zażółć"),
+            },
+            3,
+        );
+        assert_eq!(preview.text, "Za");
+        assert!(preview.truncated);
+
+        let preview = preview_of(
+            TextChunk::Text {
+                keyword: String::from("Title"),
+                text: String::from("Cat"),
+            },
+            3,
+        );
+        assert_eq!(preview.text, "Cat");
+        assert!(!preview.truncated);
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let mut data = b"Comment\0\0".to_vec();
+        data.extend(compress(&vec![b'a'; MAX_TEXT_LEN + 1]).unwrap());
+        let chunk = Chunk::new(ChunkType::from_str("zTXt").unwrap(), data);
+        let error = TextChunk::try_from(&chunk).err().unwrap();
+        assert!(error.to_string().contains("larger than allowed"));
+    }
+
+    #[test]
+    fn test_text_from_raw_chunk() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"Software\0edmipng \xa9".to_vec(),
+        );
+        let text_chunk = TextChunk::try_from(&chunk).unwrap();
+        assert_eq!(text_chunk.keyword(), "Software");
+        assert_eq!(text_chunk.text(), "edmipng ©");
+    }
+
+    #[test]
+    fn test_invalid_keyword() {
+        for keyword in [
+            "",
+            " Lead",
+            "Trail ",
+            "Two  spaces",
+            "Zażółć",
+            &"a".repeat(80),
+        ] {
+            let text_chunk = TextChunk::Text {
+                keyword: keyword.to_string(),
+                text: String::from("text"),
+            };
+            assert!(text_chunk.to_chunk().is_err());
+        }
+    }
+
+    #[test]
+    fn test_non_latin1_text() {
+        let text_chunk = TextChunk::Text {
+            keyword: String::from("Title"),
+            text: String::from("Zażółć"),
+        };
+        assert!(text_chunk.to_chunk().is_err());
+    }
+
+    #[test]
+    fn test_invalid_text_chunk() {
+        let chunk = Chunk::new(
+            ChunkType::from_str("tEXt").unwrap(),
+            b"No separator".to_vec(),
+        );
+        assert!(TextChunk::try_from(&chunk).is_err());
+
+        let chunk = Chunk::new(ChunkType::from_str("ruSt").unwrap(), b"Key\0text".to_vec());
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+}
@@ -0,0 +1,344 @@
+//! Chunk ordering and multiplicity rules of the png specification.
+
+use std::{collections::HashMap, fmt::Display};
+
+use crate::{
+    chunk_type::ChunkType,
+    ihdr::{self, Ihdr},
+    png::Png,
+};
+
+// http://www.libpng.org/pub/png/spec/1.2/PNG-Chunks.html#C.Summary-of-standard-chunks
+const KNOWN_CRITICAL_CHUNK_TYPES: [&str; 4] = ["IHDR", "PLTE", "IDAT", "IEND"];
+/// Ancillary chunks which must appear before PLTE and IDAT
+const BEFORE_PLTE_CHUNK_TYPES: [&str; 5] = ["cHRM", "gAMA", "iCCP", "sBIT", "sRGB"];
+/// Ancillary chunks which must appear after PLTE, but before IDAT
+const AFTER_PLTE_CHUNK_TYPES: [&str; 3] = ["bKGD", "hIST", "tRNS"];
+/// Ancillary chunks which must appear before IDAT
+const BEFORE_IDAT_CHUNK_TYPES: [&str; 2] = ["pHYs", "sPLT"];
+/// Standard chunks which can appear only once (other chunks, including private ones, can repeat)
+const UNIQUE_CHUNK_TYPES: [&str; 13] = [
+    "IHDR", "PLTE", "IEND", "cHRM", "gAMA", "iCCP", "sBIT", "sRGB", "bKGD", "hIST", "tRNS", "pHYs",
+    "tIME",
+];
+
+/// Errors make file invalid, warnings point out things readers may not expect
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+/// Broken rule of png structure
+#[derive(Debug, PartialEq, Eq)]
+pub struct Violation {
+    pub severity: Severity,
+    /// Index and byte offset of the offending chunk (None for problems concerning whole file)
+    pub location: Option<(usize, usize)>,
+    pub message: String,
+}
+
+impl Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        match self.location {
+            Some((index, offset)) => write!(
+                f,
+                "{}: chunk #{} at byte {}: {}",
+                severity, index, offset, self.message
+            ),
+            None => write!(f, "{}: {}", severity, self.message),
+        }
+    }
+}
+
+/// Checks chunk ordering and multiplicity rules, returning every violation found
+pub fn validate(png: &Png) -> Vec<Violation> {
+    let chunk_types: Vec<ChunkType> = png
+        .chunks()
+        .iter()
+        .map(|chunk| chunk.chunk_type().clone())
+        .collect();
+    let offsets = png.chunk_offsets();
+    let color_type = png.ihdr().ok().map(|ihdr| ihdr.color_type);
+
+    let mut violations = Vec::new();
+    // Contents of IHDR is the only rule which needs chunk data
+    if let Some(chunk) = png.chunks().first() {
+        if chunk.chunk_type().bytes() == *b"IHDR" {
+            if let Err(err) = Ihdr::try_from(chunk) {
+                violations.push(Violation {
+                    severity: Severity::Error,
+                    location: Some((0, offsets[0])),
+                    message: err.to_string(),
+                });
+            }
+        }
+    }
+    violations.extend(
+        ordering_violations(&chunk_types, color_type)
+            .into_iter()
+            .map(|(index, message)| Violation {
+                severity: Severity::Error,
+                location: index.map(|index| (index, offsets[index])),
+                message,
+            }),
+    );
+
+    let mut warning = |message: String| {
+        violations.push(Violation {
+            severity: Severity::Warning,
+            location: None,
+            message,
+        })
+    };
+    if !png.trailing_data().is_empty() {
+        warning(format!(
+            "{} bytes of data after IEND chunk",
+            png.trailing_data().len()
+        ));
+    }
+    let contains =
+        |chunk_type: &[u8; 4]| chunk_types.iter().any(|other| other.bytes() == *chunk_type);
+    if contains(b"iCCP") && contains(b"sRGB") {
+        warning(String::from(
+            "iCCP and sRGB chunks shouldn't both be present",
+        ));
+    }
+    violations
+}
+
+/// Checks rules which depend only on chunk types (and colour type from IHDR),
+/// returning error messages with index of the offending chunk (None for problems concerning whole file)
+pub fn ordering_violations(
+    types: &[ChunkType],
+    color_type: Option<u8>,
+) -> Vec<(Option<usize>, String)> {
+    let chunk_types: Vec<String> = types.iter().map(ToString::to_string).collect();
+    let first = |chunk_type: &str| chunk_types.iter().position(|other| other == chunk_type);
+    let first_plte = first("PLTE");
+    let first_idat = first("IDAT");
+
+    let mut violations = Vec::new();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for (index, chunk_type) in chunk_types.iter().enumerate() {
+        let chunk_type = chunk_type.as_str();
+        let mut error = |message: String| violations.push((Some(index), message));
+        let count = counts.entry(chunk_type).or_default();
+        *count += 1;
+
+        if !types[index].is_reserved_bit_valid() {
+            error(format!(
+                "{}: reserved bit is set (third letter must be uppercase)",
+                chunk_type
+            ));
+        }
+        if *count > 1 && UNIQUE_CHUNK_TYPES.contains(&chunk_type) {
+            error(format!("{}: chunk can appear only once", chunk_type));
+        }
+        let after_plte = first_plte.is_some_and(|plte| index > plte);
+        let after_idat = first_idat.is_some_and(|idat| index > idat);
+
+        match chunk_type {
+            "IHDR" if index != 0 => error(String::from("IHDR: must be the first chunk")),
+            "IEND" if index != chunk_types.len() - 1 => {
+                error(String::from("IEND: must be the last chunk"))
+            }
+            "PLTE" => {
+                if after_idat {
+                    error(String::from("PLTE: must appear before IDAT"));
+                }
+                if matches!(color_type, Some(ihdr::GREYSCALE | ihdr::GREYSCALE_ALPHA)) {
+                    error(String::from("PLTE: not allowed in greyscale images"));
+                }
+            }
+            "IDAT" if first_idat != Some(index) && chunk_types[index - 1] != "IDAT" => {
+                error(String::from("IDAT: chunks must be consecutive"))
+            }
+            chunk_type if BEFORE_PLTE_CHUNK_TYPES.contains(&chunk_type) => {
+                if after_plte {
+                    error(format!("{}: must appear before PLTE", chunk_type));
+                }
+                if after_idat {
+                    error(format!("{}: must appear before IDAT", chunk_type));
+                }
+            }
+            chunk_type if AFTER_PLTE_CHUNK_TYPES.contains(&chunk_type) => {
+                if first_plte.is_some_and(|plte| index < plte) {
+                    error(format!("{}: must appear after PLTE", chunk_type));
+                }
+                if chunk_type == "hIST" && first_plte.is_none() {
+                    error(String::from("hIST: requires PLTE"));
+                }
+                if after_idat {
+                    error(format!("{}: must appear before IDAT", chunk_type));
+                }
+            }
+            chunk_type if BEFORE_IDAT_CHUNK_TYPES.contains(&chunk_type) && after_idat => {
+                error(format!("{}: must appear before IDAT", chunk_type))
+            }
+            chunk_type
+                if types[index].is_critical()
+                    && !KNOWN_CRITICAL_CHUNK_TYPES.contains(&chunk_type) =>
+            {
+                error(format!(
+                    "{}: unknown critical chunk, decoders will refuse the file",
+                    chunk_type
+                ))
+            }
+            _ => {}
+        }
+    }
+
+    let mut file_error = |message: &str| violations.push((None, String::from(message)));
+    // Misplaced IHDR is already reported as chunk violation
+    if first("IHDR").is_none() {
+        file_error("File doesn't start with IHDR chunk");
+    }
+    if first_idat.is_none() {
+        file_error("File doesn't contain IDAT chunk");
+    }
+    if chunk_types
+        .last()
+        .is_none_or(|chunk_type| chunk_type != "IEND")
+    {
+        file_error("File doesn't end with IEND chunk");
+    }
+    if color_type == Some(ihdr::INDEXED) && first_plte.is_none() {
+        file_error("Indexed-colour image doesn't contain PLTE chunk");
+    }
+    violations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{chunk::Chunk, chunk_type::ChunkType};
+    use std::str::FromStr;
+
+    fn testing_png(chunk_types: &[&str]) -> Png {
+        let chunks = chunk_types
+            .iter()
+            .map(|chunk_type| {
+                let data = match *chunk_type {
+                    // 1x1 truecolor image
+                    "IHDR" => vec![0, 0, 0, 1, 0, 0, 0, 1, 8, 2, 0, 0, 0],
+                    _ => vec![],
+                };
+                Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+            })
+            .collect();
+        Png::from_chunks(chunks)
+    }
+
+    fn messages(chunk_types: &[&str]) -> Vec<String> {
+        validate(&testing_png(chunk_types))
+            .into_iter()
+            .map(|violation| violation.to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_valid_png() {
+        let chunk_types = [
+            "IHDR", "gAMA", "sRGB", "PLTE", "tRNS", "pHYs", "tEXt", "IDAT", "IDAT", "tIME", "tEXt",
+            "ruSt", "IEND",
+        ];
+        assert!(messages(&chunk_types).is_empty());
+    }
+
+    #[test]
+    fn test_ordering_violations() {
+        assert_eq!(
+            messages(&["IHDR", "IDAT", "gAMA", "IDAT", "IEND"]),
+            [
+                "error: chunk #2 at byte 45: gAMA: must appear before IDAT",
+                "error: chunk #3 at byte 57: IDAT: chunks must be consecutive",
+            ]
+        );
+        assert_eq!(
+            messages(&["IHDR", "PLTE", "sRGB", "IDAT", "PLTE", "IEND"]),
+            [
+                "error: chunk #2 at byte 45: sRGB: must appear before PLTE",
+                "error: chunk #4 at byte 69: PLTE: chunk can appear only once",
+                "error: chunk #4 at byte 69: PLTE: must appear before IDAT",
+            ]
+        );
+        assert_eq!(
+            messages(&["IHDR", "IDAT", "IEND", "tIME"]),
+            [
+                "error: chunk #2 at byte 45: IEND: must be the last chunk",
+                "error: File doesn't end with IEND chunk",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_multiplicity_violations() {
+        assert_eq!(
+            messages(&["IHDR", "IDAT", "tIME", "tIME", "IEND"]),
+            ["error: chunk #3 at byte 57: tIME: chunk can appear only once"]
+        );
+        assert!(messages(&["IHDR", "ruSt", "ruSt", "tEXt", "tEXt", "IDAT", "IEND"]).is_empty());
+        assert_eq!(
+            messages(&["IHDR", "IHDR", "IDAT", "IEND", "IEND"]),
+            [
+                "error: chunk #1 at byte 33: IHDR: chunk can appear only once",
+                "error: chunk #1 at byte 33: IHDR: must be the first chunk",
+                "error: chunk #3 at byte 70: IEND: must be the last chunk",
+                "error: chunk #4 at byte 82: IEND: chunk can appear only once",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ihdr_violations() {
+        assert_eq!(
+            messages(&["IDAT", "IHDR", "IEND"]),
+            ["error: chunk #1 at byte 20: IHDR: must be the first chunk"]
+        );
+
+        let ihdr = Chunk::new(
+            ChunkType::from_str("IHDR").unwrap(),
+            vec![0, 0, 0, 1, 0, 0, 0, 1, 3, 2, 0, 0, 0],
+        );
+        let png = Png::from_chunks(vec![
+            ihdr,
+            Chunk::new(ChunkType::from_str("IDAT").unwrap(), vec![]),
+            Chunk::new(ChunkType::from_str("IEND").unwrap(), vec![]),
+        ]);
+        let messages: Vec<String> = validate(&png).iter().map(ToString::to_string).collect();
+        assert_eq!(
+            messages,
+            ["error: chunk #0 at byte 8: Invalid IHDR bit depth 3 for color type 2: must be one of 8, 16"]
+        );
+    }
+
+    #[test]
+    fn test_chunk_type_violations() {
+        assert_eq!(
+            messages(&["IHDR", "rust", "RUst", "IDAT", "IEND"]),
+            [
+                "error: chunk #1 at byte 33: rust: reserved bit is set (third letter must be uppercase)",
+                "error: chunk #2 at byte 45: RUst: reserved bit is set (third letter must be uppercase)",
+                "error: chunk #2 at byte 45: RUst: unknown critical chunk, decoders will refuse the file",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_file_violations() {
+        assert_eq!(
+            messages(&["tEXt", "iCCP", "sRGB"]),
+            [
+                "error: File doesn't start with IHDR chunk",
+                "error: File doesn't contain IDAT chunk",
+                "error: File doesn't end with IEND chunk",
+                "warning: iCCP and sRGB chunks shouldn't both be present",
+            ]
+        );
+    }
+}
@@ -0,0 +1,259 @@
+use std::{env, fs, str::FromStr};
+
+use ed25519_dalek::SigningKey;
+use edmipng::{
+    crypto,
+    decoder::{Image, SampleBuffer},
+    encoder, fragment, ihdr, keys, lsb, payload, signature,
+    stream::{ChunkReader, ChunkWriter},
+    validation, Chunk, ChunkPosition, ChunkType, Png, PngError, PngRef, Source,
+};
+
+fn testing_png() -> Png {
+    let image = Image {
+        width: 8,
+        height: 8,
+        bit_depth: 8,
+        color_type: ihdr::GREYSCALE,
+        samples: SampleBuffer::U8((0..64).map(|value| value * 4).collect()),
+        palette: None,
+    };
+    encoder::encode(&image, encoder::DEFAULT_IDAT_CHUNK_SIZE).unwrap()
+}
+
+fn message_chunk(chunk_type: &str, message: &str) -> Chunk {
+    Chunk::new(
+        ChunkType::from_str(chunk_type).unwrap(),
+        message.as_bytes().to_vec(),
+    )
+}
+
+fn chunk_types(png: &Png) -> Vec<String> {
+    png.chunks()
+        .iter()
+        .map(|chunk| chunk.chunk_type().to_string())
+        .collect()
+}
+
+#[test]
+fn edit_and_serialize() {
+    let mut png = testing_png();
+    png.insert_chunk(message_chunk("ruSt", "hello"), ChunkPosition::BeforeIend)
+        .unwrap();
+    png.insert_chunk(
+        message_chunk("teSt", "world"),
+        ChunkPosition::Before(ChunkType::from_str("IDAT").unwrap()),
+    )
+    .unwrap();
+    assert_eq!(chunk_types(&png), ["IHDR", "teSt", "IDAT", "ruSt", "IEND"]);
+
+    let parsed = Png::try_from(png.as_bytes().as_slice()).unwrap();
+    assert_eq!(parsed.as_bytes(), png.as_bytes());
+    let chunk = parsed.chunk_by_type("ruSt").unwrap();
+    assert_eq!(chunk.data_as_string().unwrap(), "hello");
+    assert_eq!(chunk.crc(), message_chunk("ruSt", "hello").crc());
+
+    let mut parsed = parsed;
+    parsed.remove_first_chunk("teSt").unwrap();
+    assert_eq!(chunk_types(&parsed), ["IHDR", "IDAT", "ruSt", "IEND"]);
+    assert!(parsed.remove_first_chunk("teSt").is_err());
+}
+
+#[test]
+fn invalid_edits_are_refused() {
+    let mut png = testing_png();
+    assert!(png
+        .insert_chunk(message_chunk("ruSt", "x"), ChunkPosition::Index(0))
+        .is_err());
+    assert!(png
+        .insert_chunk(message_chunk("IEND", ""), ChunkPosition::BeforeIend)
+        .is_err());
+    assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "IEND"]);
+}
+
+#[test]
+fn invalid_files_are_refused() {
+    let bytes = testing_png().as_bytes();
+    assert!(Png::try_from(&bytes[1..]).is_err());
+
+    let mut corrupted = bytes.clone();
+    // Last byte of IHDR CRC
+    corrupted[32] ^= 0xFF;
+    assert!(Png::try_from(corrupted.as_slice()).is_err());
+    assert!(PngRef::parse(&corrupted).is_err());
+}
+
+#[test]
+fn errors_can_be_told_apart() {
+    let error_of = |bytes: &[u8]| {
+        let error = Png::try_from(bytes).err().unwrap();
+        error.downcast::<PngError>().unwrap()
+    };
+    let bytes = testing_png().as_bytes();
+
+    assert!(matches!(error_of(&bytes[1..]), PngError::InvalidSignature));
+    assert!(matches!(
+        error_of(&bytes[..20]),
+        PngError::InvalidLength {
+            offset: 8,
+            field: "Data",
+            expected: 13
+        }
+    ));
+    let mut corrupted = bytes.clone();
+    corrupted[32] ^= 0xFF;
+    assert!(matches!(
+        error_of(&corrupted),
+        PngError::CrcMismatch { offset: 8, .. }
+    ));
+    let mut corrupted = bytes.clone();
+    corrupted[37] = b'1';
+    assert!(matches!(
+        error_of(&corrupted),
+        PngError::InvalidChunkType {
+            offset: Some(33),
+            ..
+        }
+    ));
+
+    let error = testing_png().remove_first_chunk("ruSt").err().unwrap();
+    assert!(matches!(
+        error.downcast_ref::<PngError>(),
+        Some(PngError::MissingChunk { chunk_type, .. }) if chunk_type == "ruSt"
+    ));
+    assert!(matches!(
+        ChunkType::from_str("ru1t"),
+        Err(PngError::InvalidChunkType { offset: None, .. })
+    ));
+}
+
+#[test]
+fn borrowed_parsing() {
+    let mut png = testing_png();
+    png.insert_chunk(message_chunk("ruSt", "hello"), ChunkPosition::BeforeIend)
+        .unwrap();
+    let bytes = png.as_bytes();
+
+    let png_ref = PngRef::parse(&bytes).unwrap();
+    assert_eq!(png_ref.chunks().len(), 4);
+    assert_eq!(png_ref.chunks()[2].data(), b"hello");
+    assert!(png_ref.trailing_data().is_empty());
+    assert_eq!(png_ref.to_png().as_bytes(), bytes);
+}
+
+#[test]
+fn chunk_type_properties() {
+    let chunk_type = ChunkType::from_str("ruSt").unwrap();
+    assert!(chunk_type.is_valid());
+    assert!(!chunk_type.is_critical());
+    assert!(!chunk_type.is_public());
+    assert!(chunk_type.is_safe_to_copy());
+    assert!(chunk_type.is_potential_message());
+    assert!(ChunkType::from_str("ru1t").is_err());
+}
+
+#[test]
+fn read_from_file_source() {
+    let path = env::temp_dir().join(format!("edmipng-public-api-{}.png", std::process::id()));
+    let mut bytes = testing_png().as_bytes();
+    bytes.extend(b"trailing data");
+    fs::write(&path, &bytes).unwrap();
+
+    let source = Source::from_str(path.to_str().unwrap()).unwrap();
+    assert_eq!(source.get_output_file_path().unwrap(), path);
+    let png = Png::try_from(&source).unwrap();
+    assert_eq!(png.trailing_data(), b"trailing data");
+    assert_eq!(png.as_bytes(), bytes);
+    fs::remove_file(&path).unwrap();
+}
+
+#[test]
+fn dash_is_standard_input() {
+    let source = Source::from_str("-").unwrap();
+    assert!(matches!(source, Source::Stdin));
+    // There is no file which could be edited in place
+    assert!(source.get_output_file_path().is_err());
+}
+
+#[test]
+fn stream_copy() {
+    let mut bytes = testing_png().as_bytes();
+    bytes.extend(b"trailing data");
+
+    let mut reader = ChunkReader::new(bytes.as_slice()).unwrap();
+    let mut writer = ChunkWriter::new(Vec::new()).unwrap();
+    while let Some(header) = reader.next_header().unwrap() {
+        if header.chunk_type.to_string() == "IEND" {
+            writer.write_chunk(&message_chunk("ruSt", "hello")).unwrap();
+        }
+        reader.copy_to(&mut writer).unwrap();
+    }
+    reader.copy_trailing_data(&mut writer).unwrap();
+    let copied = writer.finish().unwrap();
+
+    let png = Png::try_from(copied.as_slice()).unwrap();
+    assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "ruSt", "IEND"]);
+    assert_eq!(png.trailing_data(), b"trailing data");
+}
+
+#[test]
+fn message_envelopes() {
+    // Same layering as `encode --compress --encrypt --fragment-size`
+    let chunk_type = ChunkType::from_str("ruSt").unwrap();
+    let message = payload::Payload {
+        content: b"hello hello hello hello".to_vec(),
+        metadata: None,
+    };
+    let wrapped = payload::wrap(&message, true).unwrap();
+    let encrypted = crypto::encrypt_with_passphrase(&wrapped, "passphrase", b"ruSt").unwrap();
+    assert!(crypto::is_encrypted(&encrypted));
+
+    let mut png = testing_png();
+    for data in fragment::split(&encrypted, 16).unwrap() {
+        png.insert_chunk(
+            Chunk::new(chunk_type.clone(), data),
+            ChunkPosition::BeforeIend,
+        )
+        .unwrap();
+    }
+    let sequences = fragment::reassemble(&png, &chunk_type).unwrap();
+    assert_eq!(sequences.len(), 1);
+    let reassembled = sequences[0].result.as_ref().unwrap();
+    let decrypted =
+        crypto::decrypt_with_passphrase(&reassembled.data, "passphrase", b"ruSt").unwrap();
+    let unwrapped = payload::unwrap(&decrypted, payload::DEFAULT_MAX_SIZE).unwrap();
+    assert_eq!(unwrapped, message);
+}
+
+#[test]
+fn signed_and_validated_file() {
+    let key = SigningKey::from_bytes(&[7; 32]);
+    let chunk = message_chunk("ruSt", "hello");
+    let signature_chunk = signature::sign_chunk(&chunk, &key).unwrap();
+    let mut png = testing_png();
+    png.insert_chunk(chunk, ChunkPosition::BeforeIend).unwrap();
+    png.insert_chunk(signature_chunk, ChunkPosition::BeforeIend)
+        .unwrap();
+
+    let checks = signature::verify_chunks(&png);
+    assert_eq!(checks.len(), 1);
+    assert!(checks[0].result.is_ok());
+    assert_eq!(checks[0].signer, Some(key.verifying_key()));
+    assert!(validation::validate(&png).is_empty());
+    assert_eq!(keys::to_hex(&[0xED, 0x01]), "ed01");
+}
+
+#[test]
+fn message_in_pixels() {
+    let image = Image {
+        width: 32,
+        height: 32,
+        bit_depth: 8,
+        color_type: ihdr::GREYSCALE,
+        samples: SampleBuffer::U8(vec![128; 32 * 32]),
+        palette: None,
+    };
+    let mut png = encoder::encode(&image, encoder::DEFAULT_IDAT_CHUNK_SIZE).unwrap();
+    lsb::embed(&mut png, b"hidden").unwrap();
+    assert_eq!(lsb::extract(&png).unwrap(), b"hidden");
+}